@@ -0,0 +1,57 @@
+//! Times reading generated application directories with `--print-commands`, run with
+//! `cargo bench`. Each run starts dmenu-desktop without the cache, so the time includes starting
+//! the process.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn read_entries(c: &mut Criterion) {
+    let root = env::temp_dir().join(format!("dmenu-desktop-bench-{}", std::process::id()));
+    let mut group = c.benchmark_group("read_entries");
+    for count in [100, 1000, 5000] {
+        let data_home = root.join(count.to_string());
+        generate(&data_home.join("applications"), count);
+        let mut command = Command::new(env!("CARGO_BIN_EXE_dmenu-desktop"));
+        command
+            .args(["--no-cache", "--print-commands", "--data-home"])
+            .arg(&data_home)
+            .arg("--data-dirs")
+            .arg(root.join("none"))
+            .env("XDG_CONFIG_HOME", root.join("config"));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| {
+                let output = command.output().unwrap();
+                assert!(output.status.success());
+                assert_eq!(output.stdout.iter().filter(|&&b| b == b'\n').count(), count);
+            })
+        });
+    }
+    group.finish();
+    let _ = fs::remove_dir_all(root);
+}
+
+/// Writes `count` desktop files like the ones applications install.
+fn generate(dir: &Path, count: usize) {
+    fs::create_dir_all(dir).unwrap();
+    for i in 0..count {
+        let contents = format!(
+            "[Desktop Entry]\nType=Application\nName=Application {i}\nName[de]=Anwendung {i}\n\
+             GenericName=Generic {i}\nComment=Does thing number {i}\nExec=app-{i} %U\n\
+             Icon=app-{i}\nCategories=Utility;Development;\nKeywords=app;number;{i};\n\
+             MimeType=text/plain;text/x-{i};\nActions=new;\n\n\
+             [Desktop Action new]\nName=New Window\nExec=app-{i} --new-window\n"
+        );
+        fs::write(path(dir, i), contents).unwrap();
+    }
+}
+
+fn path(dir: &Path, i: usize) -> PathBuf {
+    dir.join(format!("org.example.App{i}.desktop"))
+}
+
+criterion_group!(benches, read_entries);
+criterion_main!(benches);
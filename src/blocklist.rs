@@ -0,0 +1,97 @@
+//! Desktop file IDs to always hide, read from `$XDG_CONFIG_HOME/dmenu-desktop/blocklist`.
+//!
+//! Each line is a desktop file ID such as `org.gnome.Nautilus`, with or without the `.desktop`
+//! suffix, or a glob where `*` matches any characters and `?` a single one. Blank lines and lines
+//! starting with `#` are ignored.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Blocklist(Vec<String>);
+
+impl Blocklist {
+    /// Reads the blocklist at `path`, or at the default location, where a missing file is an
+    /// empty blocklist.
+    pub(crate) fn load(path: Option<&Path>) -> Blocklist {
+        let default = || config::dir().map(|d| d.join("blocklist"));
+        let Some(path) = path.map(PathBuf::from).or_else(default) else {
+            return Blocklist::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => Blocklist::parse(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Blocklist::default(),
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", path.display(), e);
+                Blocklist::default()
+            }
+        }
+    }
+
+    fn parse(contents: &str) -> Blocklist {
+        let patterns = contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| l.strip_suffix(".desktop").unwrap_or(l).to_owned())
+            .collect();
+        Blocklist(patterns)
+    }
+
+    pub(crate) fn blocks(&self, id: &str) -> bool {
+        let id: Vec<char> = id.chars().collect();
+        self.0.iter().any(|pattern| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            glob_matches(&pattern, &id)
+        })
+    }
+}
+
+/// Matches a whole text against a pattern where `*` matches any characters and `?` a single one.
+pub(crate) fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_matches(rest, &text[skip..])),
+        Some((c, rest)) => match text.split_first() {
+            Some((t, text)) => (c == t || *c == '?') && glob_matches(rest, text),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_and_suffixes() {
+        let blocklist = Blocklist::parse("# installers\n\n  ubiquity.desktop \nfoot\n");
+        assert_eq!(
+            blocklist,
+            Blocklist(vec!["ubiquity".to_owned(), "foot".to_owned()])
+        );
+        assert!(blocklist.blocks("ubiquity"));
+        assert!(!blocklist.blocks("foot-server"));
+    }
+
+    #[test]
+    fn globs() {
+        let blocklist = Blocklist::parse("org.kde.*\nxterm-?\n*-debug*\n");
+        assert!(blocklist.blocks("org.kde.dolphin"));
+        assert!(!blocklist.blocks("org.kde"));
+        assert!(blocklist.blocks("xterm-1"));
+        assert!(!blocklist.blocks("xterm-10"));
+        assert!(blocklist.blocks("app-debug"));
+        assert!(blocklist.blocks("app-debug-symbols"));
+        assert!(!blocklist.blocks("app"));
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let path = Path::new("/nonexistent/blocklist");
+        assert_eq!(Blocklist::load(Some(path)), Blocklist::default());
+    }
+}
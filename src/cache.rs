@@ -0,0 +1,258 @@
+//! On-disk cache of the desktop files found in the application directories.
+//!
+//! The cache holds the source of every desktop file rather than the resulting entries, since
+//! hiding and localization depend on the environment of each run: TryExec in particular is looked
+//! up in $PATH, which changes whenever programs are installed. Loading it replaces walking the
+//! directories and reading each file with a single read and a look at the modification time of
+//! each file, which the entries need anyway, but the sources are still parsed on every run.
+//!
+//! The cache is invalidated whenever the modification time of an application directory or one of
+//! its subdirectories changes, which happens when files are added, removed or renamed, and when
+//! the modification time of one of the files changes, which happens when it is edited in place.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, fs, process};
+
+use crate::config;
+use crate::discovery::{directory_tree, modified, DesktopFile};
+
+const HEADER: &str = "dmenu-desktop cache 3";
+
+/// Returns the directory holding the cache and the launch history.
+pub fn dir() -> Option<PathBuf> {
+    let cache_home = match env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        Some(cache_home) => PathBuf::from(cache_home),
+        None => config::home()?.join(".cache"),
+    };
+    Some(cache_home.join("dmenu-desktop"))
+}
+
+/// Returns the newest modification time of each directory and its subdirectories, or `None` for
+/// directories that don't exist.
+pub fn dir_mtimes(app_dirs: &[PathBuf]) -> Vec<Option<Duration>> {
+    app_dirs
+        .iter()
+        .map(|app_dir| {
+            directory_tree(app_dir)
+                .iter()
+                .filter_map(|(dir, _)| since_epoch(modified(dir)))
+                .max()
+        })
+        .collect()
+}
+
+/// Loads the cached desktop files, or `None` if the cache is missing, corrupt or out of date,
+/// including when one of the files was modified since it was cached.
+pub fn load(app_dirs: &[PathBuf], mtimes: &[Option<Duration>]) -> Option<Vec<DesktopFile>> {
+    load_from(&dir()?.join("cache"), app_dirs, mtimes)
+}
+
+pub fn save(
+    app_dirs: &[PathBuf],
+    mtimes: &[Option<Duration>],
+    files: &[DesktopFile],
+) -> io::Result<()> {
+    let Some(dir) = dir() else {
+        return Err(io::Error::other("No cache directory found."));
+    };
+    save_to(&dir.join("cache"), app_dirs, mtimes, files)
+}
+
+fn load_from(
+    path: &Path,
+    app_dirs: &[PathBuf],
+    mtimes: &[Option<Duration>],
+) -> Option<Vec<DesktopFile>> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut rest = contents.strip_prefix(HEADER)?.strip_prefix('\n')?;
+
+    for (app_dir, mtime) in app_dirs.iter().zip(mtimes) {
+        let (line, remaining) = rest.split_once('\n')?;
+        rest = remaining;
+        let (cached_mtime, cached_dir) = line.strip_prefix("dir\t")?.split_once('\t')?;
+        if cached_mtime != format_mtime(*mtime) || Path::new(cached_dir) != app_dir {
+            return None;
+        }
+    }
+
+    let mut files = Vec::new();
+    while !rest.is_empty() {
+        let (line, remaining) = rest.split_once('\n')?;
+        let mut fields = line.strip_prefix("file\t")?.splitn(4, '\t');
+        let len: usize = fields.next()?.parse().ok()?;
+        let cached_mtime = fields.next()?;
+        let id = fields.next()?.to_owned();
+        let path = PathBuf::from(fields.next()?);
+        let mtime = modified(&path);
+        if cached_mtime != format_mtime(since_epoch(mtime)) {
+            return None;
+        }
+        let source = remaining.get(..len)?.to_owned();
+        rest = remaining.get(len..)?.strip_prefix('\n')?;
+        files.push(DesktopFile {
+            id,
+            path,
+            source,
+            mtime,
+        });
+    }
+    Some(files)
+}
+
+fn save_to(
+    path: &Path,
+    app_dirs: &[PathBuf],
+    mtimes: &[Option<Duration>],
+    files: &[DesktopFile],
+) -> io::Result<()> {
+    let mut contents = format!("{}\n", HEADER);
+    for (app_dir, mtime) in app_dirs.iter().zip(mtimes) {
+        let app_dir = app_dir.to_str().filter(|d| !d.contains('\n'));
+        let Some(app_dir) = app_dir else {
+            return Err(io::Error::other("Directory can't be stored in the cache."));
+        };
+        contents.push_str(&format!("dir\t{}\t{}\n", format_mtime(*mtime), app_dir));
+    }
+    for file in files {
+        let path = file.path.to_str().filter(|p| !p.contains('\n'));
+        let (Some(path), false) = (path, file.id.contains(['\t', '\n'])) else {
+            return Err(io::Error::other("File can't be stored in the cache."));
+        };
+        contents.push_str(&format!(
+            "file\t{}\t{}\t{}\t{}\n",
+            file.source.len(),
+            format_mtime(since_epoch(file.mtime)),
+            file.id,
+            path
+        ));
+        contents.push_str(&file.source);
+        contents.push('\n');
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write to a temporary file first so concurrent runs never see a partial cache.
+    let temp = path.with_extension(format!("tmp{}", process::id()));
+    fs::File::create(&temp)?.write_all(contents.as_bytes())?;
+    fs::rename(temp, path)
+}
+
+fn since_epoch(time: Option<SystemTime>) -> Option<Duration> {
+    time?.duration_since(UNIX_EPOCH).ok()
+}
+
+fn format_mtime(mtime: Option<Duration>) -> String {
+    match mtime {
+        Some(mtime) => format!("{}.{:09}", mtime.as_secs(), mtime.subsec_nanos()),
+        None => "-".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_dir, write};
+
+    /// Writes desktop files in `dir` and returns them as they were read.
+    fn files(dir: &Path) -> Vec<DesktopFile> {
+        [
+            (
+                "firefox",
+                "firefox.desktop",
+                "[Desktop Entry]\nName=Firefox\n",
+            ),
+            (
+                "no-newline",
+                "my apps\t/no-newline.desktop",
+                "[Desktop Entry]\nName=Ünïcode",
+            ),
+        ]
+        .into_iter()
+        .map(|(id, name, source)| {
+            let path = dir.join(name);
+            write(&path, source);
+            DesktopFile {
+                id: id.to_owned(),
+                mtime: modified(&path),
+                path,
+                source: source.to_owned(),
+            }
+        })
+        .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = temp_dir("cache-round-trip");
+        let cache = dir.join("cache");
+        let files = files(&dir.join("apps"));
+        let app_dirs = [dir.join("apps"), PathBuf::from("/missing")];
+        let mtimes = [Some(Duration::new(1700000000, 42)), None];
+        save_to(&cache, &app_dirs, &mtimes, &files).unwrap();
+        assert_eq!(load_from(&cache, &app_dirs, &mtimes), Some(files));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stale_cache() {
+        let dir = temp_dir("cache-stale");
+        let cache = dir.join("cache");
+        let app_dirs = [PathBuf::from("/apps")];
+        let mtimes = [Some(Duration::from_secs(1))];
+        save_to(&cache, &app_dirs, &mtimes, &files(&dir)).unwrap();
+        assert_eq!(
+            load_from(&cache, &app_dirs, &[Some(Duration::from_secs(2))]),
+            None
+        );
+        assert_eq!(load_from(&cache, &app_dirs, &[None]), None);
+        assert_eq!(load_from(&cache, &[PathBuf::from("/other")], &mtimes), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_cache() {
+        let dir = temp_dir("cache-corrupt");
+        let cache = dir.join("cache");
+        let app_dirs = [PathBuf::from("/apps")];
+        let mtimes = [Some(Duration::from_secs(1))];
+        save_to(&cache, &app_dirs, &mtimes, &files(&dir)).unwrap();
+        let contents = fs::read_to_string(&cache).unwrap();
+
+        fs::write(&cache, contents.replace("cache 3", "cache 2")).unwrap();
+        assert_eq!(load_from(&cache, &app_dirs, &mtimes), None);
+        fs::write(&cache, &contents[..contents.len() - 5]).unwrap();
+        assert_eq!(load_from(&cache, &app_dirs, &mtimes), None);
+        fs::write(&cache, [0xff, 0xfe]).unwrap();
+        assert_eq!(load_from(&cache, &app_dirs, &mtimes), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn files_edited_in_place() {
+        let dir = temp_dir("cache-edited");
+        let cache = dir.join("cache");
+        let files = files(&dir.join("apps"));
+        let app_dirs = [dir.join("apps")];
+        let mtimes = dir_mtimes(&app_dirs);
+        save_to(&cache, &app_dirs, &mtimes, &files).unwrap();
+        assert!(load_from(&cache, &app_dirs, &mtimes).is_some());
+
+        // Editing a file in place changes the file's modification time but not the directory's.
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .open(&files[0].path)
+            .unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1700000000))
+            .unwrap();
+        assert_eq!(dir_mtimes(&app_dirs), mtimes);
+        assert_eq!(load_from(&cache, &app_dirs, &mtimes), None);
+
+        fs::remove_file(&files[1].path).unwrap();
+        save_to(&cache, &app_dirs, &mtimes, &files[1..]).unwrap();
+        assert_eq!(load_from(&cache, &app_dirs, &mtimes), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
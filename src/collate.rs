@@ -0,0 +1,165 @@
+//! Sort keys approximating alphabetical order for Latin-script names.
+//!
+//! Names are compared with case and the diacritics of Latin letters ignored first, so that
+//! "Éditeur" sorts with the other names starting with an e, then by case-insensitive spelling and
+//! finally by exact spelling, which keeps the order deterministic. Letters of other scripts are
+//! compared by their lowercase code points.
+//!
+//! Natural keys compare the same way, except that runs of digits are compared by their value, so
+//! that "Track 2" sorts before "Track 10".
+
+/// Returns a key that orders names alphabetically.
+pub fn key(name: &str) -> (String, String, String) {
+    let lowercase = name.to_lowercase();
+    let mut folded = String::with_capacity(lowercase.len());
+    for c in lowercase.chars() {
+        match fold(c) {
+            Some(base) => folded.push_str(base),
+            None => folded.push(c),
+        }
+    }
+    (folded, lowercase, name.to_owned())
+}
+
+/// A part of a natural sort key. Numbers sort before text at the same position, as digits do
+/// before letters.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Chunk {
+    /// A run of digits without its leading zeros, compared by length first and so by value.
+    Number {
+        digits: usize,
+        value: String,
+    },
+    Text(String),
+}
+
+/// Returns a key that orders names alphabetically, comparing the numbers in them by value.
+pub fn natural_key(name: &str) -> (Vec<Chunk>, Vec<Chunk>, String) {
+    let (folded, lowercase, name) = key(name);
+    (chunks(&folded), chunks(&lowercase), name)
+}
+
+fn chunks(s: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut rest = s;
+    while let Some(first) = rest.chars().next() {
+        let is_number = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_number)
+            .unwrap_or(rest.len());
+        let (chunk, after) = rest.split_at(end);
+        chunks.push(match is_number {
+            true => {
+                let value = chunk.trim_start_matches('0');
+                Chunk::Number {
+                    digits: value.len(),
+                    value: value.to_owned(),
+                }
+            }
+            false => Chunk::Text(chunk.to_owned()),
+        });
+        rest = after;
+    }
+    chunks
+}
+
+/// Folds the case of a string for comparisons ignoring case, lowercasing it and replacing the
+/// lowercase letters with no uppercase counterpart of their own.
+pub fn fold_case(s: &str) -> String {
+    s.to_lowercase()
+        .chars()
+        .fold(String::with_capacity(s.len()), |mut folded, c| {
+            match c {
+                'ß' => folded.push_str("ss"),
+                'ς' => folded.push('σ'),
+                c => folded.push(c),
+            }
+            folded
+        })
+}
+
+/// Returns the base letters of a lowercase Latin letter with diacritics or a ligature.
+fn fold(c: char) -> Option<&'static str> {
+    let base = match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ð' | 'ď' | 'đ' => "d",
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'þ' => "th",
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    };
+    Some(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        names.sort_by_key(|n| key(n));
+        names
+    }
+
+    #[test]
+    fn accents_sort_with_base_letters() {
+        assert_eq!(
+            sorted(&["Zoom", "Éditeur", "Editor", "Ångström", "Archive", "Œuvre"]),
+            ["Ångström", "Archive", "Éditeur", "Editor", "Œuvre", "Zoom"]
+        );
+        assert_eq!(sorted(&["Straße", "Strasse", "Strbe"])[2], "Strbe");
+    }
+
+    #[test]
+    fn case_only_breaks_ties() {
+        assert_eq!(
+            sorted(&["foot", "Foot", "FOOT", "firefox", "GIMP"]),
+            ["firefox", "FOOT", "Foot", "foot", "GIMP"]
+        );
+        assert_eq!(sorted(&["Éa", "ea", "Eb"]), ["ea", "Éa", "Eb"]);
+    }
+
+    #[test]
+    fn numbers_sort_by_value() {
+        let mut names = [
+            "Track 10", "track 2", "Track 2", "7-Zip", "2048", "Kdenlive", "Track 02", "Éa 9",
+            "ea 10",
+        ];
+        names.sort_by_key(|n| natural_key(n));
+        assert_eq!(
+            names,
+            [
+                "7-Zip", "2048", "Éa 9", "ea 10", "Kdenlive", "Track 02", "Track 2", "track 2",
+                "Track 10"
+            ]
+        );
+        let huge = "App 123456789012345678901234567890";
+        assert!(natural_key("App 99") < natural_key(huge));
+    }
+
+    #[test]
+    fn folded_case() {
+        assert_eq!(fold_case("GNOME Terminal"), "gnome terminal");
+        assert_eq!(fold_case("STRASSE"), fold_case("Straße"));
+        assert_eq!(fold_case("ΟΔΟΣ"), fold_case("οδος"));
+        assert_ne!(fold_case("Éditeur"), fold_case("Editeur"));
+    }
+}
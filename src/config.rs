@@ -0,0 +1,168 @@
+//! Default options from `$XDG_CONFIG_HOME/dmenu-desktop/config`.
+//!
+//! Each key of the config file is the name of a long option with underscores, such as
+//! `entry_type = command` for `--entry-type command`. Flags take `true` or `false`, and options
+//! that can be repeated can be given more than once. The options of the config file are passed
+//! before the ones of the command line, so that the command line overrides them.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process;
+
+use clap::{CommandFactory, Parser};
+use ini::Ini;
+
+use crate::Cli;
+
+/// Returns `$HOME`, unless it is unset or not an absolute path as in some minimal session
+/// environments.
+pub(crate) fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .filter(|home| home.is_absolute())
+}
+
+/// Returns `$XDG_CONFIG_HOME`, defaulting to `~/.config`.
+pub(crate) fn config_home() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(config_home) => Some(PathBuf::from(config_home)),
+        None => Some(home()?.join(".config")),
+    }
+}
+
+/// Returns the directory of the config file and the blocklist.
+pub(crate) fn dir() -> Option<PathBuf> {
+    Some(config_home()?.join("dmenu-desktop"))
+}
+
+fn path() -> Option<PathBuf> {
+    Some(dir()?.join("config"))
+}
+
+/// Parses the command line on top of the config file, ignoring the config file with a warning
+/// when it can't be used.
+pub fn parse_cli() -> Cli {
+    let args: Vec<OsString> = env::args_os().collect();
+    // A client shows the menu with the options of the daemon, which already read the config file.
+    if let Some(cli) = Cli::try_parse_from(&args).ok().filter(|cli| cli.client) {
+        return cli;
+    }
+    let Some(path) = path().filter(|p| p.exists()) else {
+        return parse_or_exit(&args);
+    };
+    let ini = Ini::load_from_file_opt(
+        &path,
+        ini::ParseOption {
+            enabled_quote: false,
+            enabled_escape: false,
+        },
+    );
+    let config_args = match ini
+        .map_err(|e| e.to_string())
+        .and_then(|ini| config_args(&ini))
+    {
+        Ok(config_args) => config_args,
+        Err(e) => {
+            eprintln!("Ignoring {}: {}", path.display(), e);
+            return parse_or_exit(&args);
+        }
+    };
+
+    let with_config = args
+        .iter()
+        .take(1)
+        .cloned()
+        .chain(config_args.into_iter().map(OsString::from))
+        .chain(args.iter().skip(1).cloned());
+    // Errors caused by the command line itself are reported when parsing it without the config.
+    Cli::try_parse_from(with_config).unwrap_or_else(|e| {
+        if Cli::try_parse_from(&args).is_ok() {
+            eprintln!("Ignoring {}: {}", path.display(), e.kind());
+        }
+        parse_or_exit(&args)
+    })
+}
+
+/// Parses the command line or exits, with 1 for invalid options rather than the 2 of clap, which
+/// is the exit code of a cancelled menu.
+fn parse_or_exit(args: &[OsString]) -> Cli {
+    Cli::try_parse_from(args).unwrap_or_else(|e| {
+        let _ = e.print();
+        // --help and --version are reported as errors too.
+        process::exit(match e.use_stderr() {
+            true => 1,
+            false => 0,
+        })
+    })
+}
+
+/// Converts the config file to command line options.
+fn config_args(ini: &Ini) -> Result<Vec<String>, String> {
+    let command = Cli::command();
+    let mut args = Vec::new();
+    for (section, properties) in ini.iter() {
+        if let Some(section) = section {
+            return Err(format!("unexpected section [{}]", section));
+        }
+        for (key, value) in properties.iter() {
+            let arg = command
+                .get_arguments()
+                .find(|a| a.get_id() == key && a.get_long().is_some())
+                .ok_or_else(|| format!("unknown option {}", key))?;
+            let long = format!("--{}", arg.get_long().unwrap_or_default());
+            match (arg.get_action().takes_values(), value) {
+                (true, value) => args.push(format!("{}={}", long, value)),
+                (false, "true") => args.push(long),
+                (false, "false") => {}
+                (false, value) => {
+                    return Err(format!("{} must be true or false, not {}", key, value))
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sort;
+
+    fn args(config: &str) -> Result<Vec<String>, String> {
+        config_args(&Ini::load_from_str(config).unwrap())
+    }
+
+    #[test]
+    fn options_and_flags() {
+        let config = "dmenu = rofi -dmenu\nsort = frecency\nshow_all = true\nno_cache = false\n\
+            include_dir = /a\ninclude_dir = /b\n";
+        assert_eq!(
+            args(config).unwrap(),
+            [
+                "--dmenu=rofi -dmenu",
+                "--sort=frecency",
+                "--show-all",
+                "--include-dir=/a",
+                "--include-dir=/b"
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_config() {
+        assert!(args("unknown = 1\n").is_err());
+        assert!(args("show_all = yes\n").is_err());
+        assert!(args("[section]\ndmenu = dmenu\n").is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let mut argv = vec!["dmenu-desktop".to_owned()];
+        argv.extend(args("dmenu = rofi -dmenu\nsort = frecency\n").unwrap());
+        argv.push("--dmenu=fuzzel --dmenu".to_owned());
+        let cli = Cli::try_parse_from(argv).unwrap();
+        assert_eq!(cli.dmenu.as_deref(), Some("fuzzel --dmenu"));
+        assert!(matches!(cli.sort, Sort::Frecency));
+    }
+}
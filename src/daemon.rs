@@ -0,0 +1,151 @@
+//! Daemon mode, which keeps the parsed entries in memory and shows the menu whenever a client
+//! connects to its socket.
+//!
+//! The menu is shown with the options the daemon was started with, so a client takes none of its
+//! own. Clients are served one at a time: one connecting while the menu is open waits for it to
+//! close and then gets a menu of its own, rather than two menus competing for the keyboard.
+//!
+//! Before each menu the daemon compares the modification times of the application directories
+//! and of the desktop file of each entry with the ones seen when the entries were read, and
+//! rereads the entries if any of them changed. Directories change when files are added, removed or
+//! renamed, and files when they are edited in place.
+
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, fs};
+
+use crate::discovery::{modified, read_entries};
+use crate::entry::{DesktopEntry, Environment};
+use crate::format::Format;
+use crate::overrides::Overrides;
+use crate::{cache, force_visibility, show_menu, sort_entries, Cli};
+
+fn socket_path() -> io::Result<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(runtime_dir) => Ok(PathBuf::from(runtime_dir).join("dmenu-desktop.sock")),
+        None => Err(io::Error::other("$XDG_RUNTIME_DIR is not set.")),
+    }
+}
+
+/// Listens for clients until the process is killed.
+pub fn serve(cli: &Cli, format: &Format, app_dirs: &[PathBuf]) -> io::Result<()> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(io::Error::other("A daemon is already running."));
+    }
+    // A socket nobody listens on is left over from a daemon that didn't exit cleanly.
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+
+    let mut mtimes = cache::dir_mtimes(app_dirs);
+    let mut entries = read(cli, app_dirs);
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+
+        refresh(cli, app_dirs, &mut mtimes, &mut entries);
+        sort_entries(cli, &mut entries);
+
+        let code = match show_menu(cli, format, &entries) {
+            Ok(()) => 0,
+            Err(e) => {
+                e.report();
+                e.exit_code()
+            }
+        };
+        let _ = stream.write_all(&[code]);
+    }
+    Ok(())
+}
+
+fn read(cli: &Cli, app_dirs: &[PathBuf]) -> Vec<DesktopEntry> {
+    // A new environment lists $PATH again, for the TryExec of newly installed programs.
+    let environment = Environment {
+        include_links: cli.include_links,
+        ..Environment::new(cli.desktop.as_deref())
+    };
+    let mut entries: Vec<DesktopEntry> = read_entries(
+        app_dirs,
+        &environment,
+        &cli.dedup_by,
+        !cli.no_cache,
+        cli.verbose,
+    )
+    .into_values()
+    .collect();
+    Overrides::load(cli.overrides.as_deref()).apply(&mut entries, cli.verbose);
+    force_visibility(cli, &mut entries);
+    entries
+}
+
+/// Rereads the entries when one of the application directories or the desktop file of one of the
+/// entries was modified since they were read, returning whether they were.
+fn refresh(
+    cli: &Cli,
+    app_dirs: &[PathBuf],
+    mtimes: &mut Vec<Option<Duration>>,
+    entries: &mut Vec<DesktopEntry>,
+) -> bool {
+    let current = cache::dir_mtimes(app_dirs);
+    let edited = || entries.iter().any(|e| modified(&e.location) != e.mtime);
+    if current == *mtimes && !edited() {
+        return false;
+    }
+    *mtimes = current;
+    *entries = read(cli, app_dirs);
+    true
+}
+
+/// Asks the daemon to show the menu, returning the exit code it reports.
+pub fn client() -> io::Result<u8> {
+    let mut stream = UnixStream::connect(socket_path()?)?;
+    let mut code = [0];
+    stream.read_exact(&mut code)?;
+    Ok(code[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::test_support::{temp_dir, write};
+
+    #[test]
+    fn files_edited_in_place_are_reread() {
+        let root = temp_dir("daemon");
+        let app_dirs = [root.join("applications")];
+        let file = app_dirs[0].join("app.desktop");
+        let source =
+            |exec: &str| format!("[Desktop Entry]\nType=Application\nName=App\nExec={exec}\n");
+        write(&file, &source("old"));
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--no-cache",
+            "--overrides",
+            "/nonexistent/overrides",
+        ]);
+        let mut mtimes = cache::dir_mtimes(&app_dirs);
+        let mut entries = read(&cli, &app_dirs);
+        assert!(!refresh(&cli, &app_dirs, &mut mtimes, &mut entries));
+
+        fs::write(&file, source("new")).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(cache::dir_mtimes(&app_dirs), mtimes);
+        assert!(refresh(&cli, &app_dirs, &mut mtimes, &mut entries));
+        assert_eq!(entries[0].exec, "new");
+        assert!(!refresh(&cli, &app_dirs, &mut mtimes, &mut entries));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+//! Launching of DBusActivatable entries through the `org.freedesktop.Application` interface,
+//! by calling `gdbus` rather than linking a D-Bus implementation.
+
+use std::env;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::entry::{DesktopAction, DesktopEntry};
+
+/// Activates the entry, or one of its actions, over the session bus, opening `args` if given.
+/// Returns whether the call succeeded.
+pub fn activate(entry: &DesktopEntry, action: Option<&DesktopAction>, args: &[String]) -> bool {
+    if env::var_os("DBUS_SESSION_BUS_ADDRESS").is_none() {
+        return false;
+    }
+
+    let mut command = Command::new("gdbus");
+    command.args(["call", "--session", "--dest", &entry.filename]);
+    command.args(["--object-path", &object_path(&entry.filename)]);
+    match (action, args) {
+        (Some(action), _) => command.args([
+            "--method",
+            "org.freedesktop.Application.ActivateAction",
+            &string(&action.id),
+            "[]",
+            "{}",
+        ]),
+        (None, []) => command.args(["--method", "org.freedesktop.Application.Activate", "{}"]),
+        (None, args) => {
+            let uris: Vec<String> = args.iter().map(|a| string(&uri(a))).collect();
+            command.args([
+                "--method",
+                "org.freedesktop.Application.Open",
+                &format!("[{}]", uris.join(", ")),
+                "{}",
+            ])
+        }
+    };
+    command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Returns the object path for an application ID, per the desktop entry specification.
+fn object_path(id: &str) -> String {
+    let path: String = id
+        .chars()
+        .map(|c| match c {
+            '.' => '/',
+            '-' => '_',
+            c => c,
+        })
+        .collect();
+    format!("/{}", path)
+}
+
+/// Turns a file argument into a `file://` URI, leaving URIs as they are.
+fn uri(arg: &str) -> String {
+    if arg.contains("://") {
+        return arg.to_owned();
+    }
+    let path = Path::new(arg);
+    let path = match path.is_absolute() {
+        true => path.to_path_buf(),
+        false => env::current_dir().unwrap_or_default().join(path),
+    };
+    let mut uri = "file://".to_owned();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Formats a string in the GVariant text format.
+fn string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_paths() {
+        assert_eq!(object_path("org.gnome.Nautilus"), "/org/gnome/Nautilus");
+        assert_eq!(object_path("org.example.my-app"), "/org/example/my_app");
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(uri("https://example.org"), "https://example.org");
+        assert_eq!(uri("/home/me/a b.txt"), "file:///home/me/a%20b.txt");
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(string("it's"), "'it\\'s'");
+    }
+}
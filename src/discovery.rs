@@ -0,0 +1,721 @@
+//! Finding the application directories and reading the desktop entries in them.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{env, fs, io, thread};
+
+use ini::Ini;
+
+use crate::blocklist::glob_matches;
+use crate::cache;
+use crate::config;
+use crate::entry::{DesktopEntry, Environment, Skip};
+use crate::{Cli, DedupBy};
+
+/// Returns the ordered application directory search path, with the --include-dir directories
+/// searched after the XDG directories unless --prepend-dirs is given. --data-home and --data-dirs
+/// stand in for their environment variables.
+pub(crate) fn application_dirs(cli: &Cli) -> Vec<PathBuf> {
+    let (data_home, home, data_dirs) = data_dir_vars(cli);
+    if cli.verbose && data_home.is_none() && home.is_none() {
+        eprintln!("Neither $XDG_DATA_HOME nor $HOME is set, only reading the system applications");
+    }
+    let xdg_dirs = application_dirs_from(data_home, home, data_dirs);
+    let include_dirs: Vec<PathBuf> = cli
+        .include_dir
+        .iter()
+        .flat_map(|dir| expand_include_dir(dir, cli.verbose))
+        .collect();
+    let (first, second) = match cli.prepend_dirs {
+        true => (include_dirs, xdg_dirs),
+        false => (xdg_dirs, include_dirs),
+    };
+    dedup_dirs(first.into_iter().chain(second))
+}
+
+/// Expands a leading `~` and the `*` and `?` globs of an --include-dir to the directories it matches,
+/// in alphabetical order. Globs don't match hidden files unless they start with a dot themselves.
+fn expand_include_dir(pattern: &Path, verbose: bool) -> Vec<PathBuf> {
+    let pattern = match (pattern.strip_prefix("~"), config::home()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => pattern.to_owned(),
+    };
+    let is_glob = |s: &str| s.contains(['*', '?']);
+    if !pattern.to_str().is_some_and(is_glob) {
+        return vec![pattern];
+    }
+
+    let mut paths = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let component = component.as_os_str();
+        let Some(glob) = component.to_str().filter(|c| is_glob(c)) else {
+            paths.iter_mut().for_each(|path| path.push(component));
+            continue;
+        };
+        let glob: Vec<char> = glob.chars().collect();
+        let mut matches = Vec::new();
+        for path in &paths {
+            let dir = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            };
+            let Ok(read_dir) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut names: Vec<OsString> = read_dir
+                .flatten()
+                .map(|entry| entry.file_name())
+                .filter(|name| {
+                    let name: Vec<char> = name.to_string_lossy().chars().collect();
+                    (name.first() != Some(&'.') || glob.first() == Some(&'.'))
+                        && glob_matches(&glob, &name)
+                })
+                .collect();
+            names.sort();
+            matches.extend(names.into_iter().map(|name| path.join(name)));
+        }
+        paths = matches;
+    }
+    paths.retain(|path| path.is_dir());
+    if paths.is_empty() && verbose {
+        eprintln!("--include-dir {} matches no directory", pattern.display());
+    }
+    paths
+}
+
+/// Returns the XDG data directories, which hold the applications and icons directories.
+pub(crate) fn data_dirs(cli: &Cli) -> Vec<PathBuf> {
+    let (data_home, home, data_dirs) = data_dir_vars(cli);
+    data_dirs_from(data_home, home, data_dirs)
+}
+
+/// Returns `$XDG_DATA_HOME` or --data-home, `$HOME` and `$XDG_DATA_DIRS` or --data-dirs.
+fn data_dir_vars(cli: &Cli) -> (Option<OsString>, Option<OsString>, Option<OsString>) {
+    let data_home = cli
+        .data_home
+        .clone()
+        .map(OsString::from)
+        .or_else(|| env::var_os("XDG_DATA_HOME"))
+        .filter(|d| !d.is_empty());
+    let data_dirs = cli
+        .data_dirs
+        .clone()
+        .or_else(|| env::var_os("XDG_DATA_DIRS"));
+    (data_home, config::home().map(OsString::from), data_dirs)
+}
+
+/// Builds the application directory search path from the values of `$XDG_DATA_HOME`, `$HOME` and
+/// `$XDG_DATA_DIRS`, treating empty values as unset and dropping repeated directories.
+fn application_dirs_from(
+    data_home: Option<OsString>,
+    home: Option<OsString>,
+    data_dirs: Option<OsString>,
+) -> Vec<PathBuf> {
+    dedup_dirs(
+        data_dirs_from(data_home, home, data_dirs)
+            .into_iter()
+            .map(|dir| dir.join("applications")),
+    )
+}
+
+fn data_dirs_from(
+    data_home: Option<OsString>,
+    home: Option<OsString>,
+    data_dirs: Option<OsString>,
+) -> Vec<PathBuf> {
+    let data_home = match data_home.filter(|d| !d.is_empty()) {
+        Some(data_home) => Some(PathBuf::from(data_home)),
+        None => home
+            .map(PathBuf::from)
+            .filter(|home| home.is_absolute())
+            .map(|home| home.join(".local/share")),
+    };
+    let data_dirs: Vec<PathBuf> = match data_dirs.filter(|d| !d.is_empty()) {
+        Some(dirs) => env::split_paths(&dirs).collect(),
+        None => vec![
+            PathBuf::from("/usr/local/share"),
+            PathBuf::from("/usr/share"),
+        ],
+    };
+
+    data_home.into_iter().chain(data_dirs).collect()
+}
+
+/// Lists the directories one per line, marking the ones that don't exist.
+pub(crate) fn describe_dirs(app_dirs: &[PathBuf]) -> String {
+    let mut description = String::new();
+    for dir in app_dirs {
+        description.push_str(&dir.to_string_lossy());
+        if !dir.is_dir() {
+            description.push_str(" (missing)");
+        }
+        description.push('\n');
+    }
+    description
+}
+
+/// Collects directories, keeping only the first occurrence of each.
+fn dedup_dirs(dirs: impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut app_dirs = Vec::new();
+    for dir in dirs {
+        if !app_dirs.contains(&dir) {
+            app_dirs.push(dir);
+        }
+    }
+    app_dirs
+}
+
+/// Returns the desktop entries in the given application directories by desktop file ID, earlier
+/// directories taking precedence. A map ordered by ID keeps every run listing them the same way.
+pub(crate) fn read_entries(
+    app_dirs: &[PathBuf],
+    environment: &Environment,
+    dedup_by: &DedupBy,
+    use_cache: bool,
+    verbose: bool,
+) -> BTreeMap<String, DesktopEntry> {
+    let files = match use_cache {
+        true => {
+            let mtimes = cache::dir_mtimes(app_dirs);
+            cache::load(app_dirs, &mtimes).unwrap_or_else(|| {
+                let files = read_desktop_files(app_dirs, verbose);
+                // The cache is only an optimization, the entries are still usable without it.
+                let _ = cache::save(app_dirs, &mtimes, &files);
+                files
+            })
+        }
+        false => read_desktop_files(app_dirs, verbose),
+    };
+    let parsed = parallel_map(&files, |file| parse_file(file, environment));
+    if verbose {
+        for (file, entry) in files.iter().zip(&parsed) {
+            match entry {
+                Err(skip) => eprintln!("Skipping {}: {}", file.path.display(), skip),
+                Ok(DesktopEntry {
+                    hidden: Some(hidden),
+                    ..
+                }) => eprintln!("Hiding {}: {}", file.path.display(), hidden),
+                Ok(_) => {}
+            }
+        }
+        for (shadowed, first) in shadowed_files(&files, &parsed) {
+            eprintln!(
+                "Ignoring {}: shadowed by {}",
+                shadowed.display(),
+                first.display()
+            );
+        }
+    }
+    merge_entries(files, parsed, dedup_by)
+}
+
+/// The source of a desktop file.
+#[derive(Debug, PartialEq)]
+pub(crate) struct DesktopFile {
+    pub(crate) id: String,
+    pub(crate) path: PathBuf,
+    pub(crate) source: String,
+    /// The modification time of the file when the source was read.
+    pub(crate) mtime: Option<SystemTime>,
+}
+
+/// Reads every desktop file in the application directories, in search order.
+fn read_desktop_files(app_dirs: &[PathBuf], verbose: bool) -> Vec<DesktopFile> {
+    let paths = desktop_files(app_dirs);
+    let sources = parallel_map(&paths, |(_, path)| {
+        // The time is taken first, so that an edit while reading leaves the source older than it.
+        let mtime = modified(path);
+        Ok::<_, io::Error>((fs::read_to_string(path)?, mtime))
+    });
+    paths
+        .into_iter()
+        .zip(sources)
+        .filter_map(|((id, path), source)| match source {
+            Ok((source, mtime)) => Some(DesktopFile {
+                id,
+                path,
+                source,
+                mtime,
+            }),
+            Err(e) => {
+                if verbose {
+                    eprintln!("Skipping {}: {}", path.display(), e);
+                }
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns the desktop file ID and path of each desktop file, in search order. The ID of a file in
+/// a subdirectory is its path relative to the application directory with slashes replaced by
+/// dashes, without the `.desktop` extension.
+fn desktop_files(app_dirs: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    for app_dir in app_dirs {
+        for (dir, prefix) in directory_tree(app_dir) {
+            for path in dir_contents(&dir) {
+                let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                let stem = name.strip_suffix(".desktop").filter(|s| !s.is_empty());
+                if let Some(stem) = stem.filter(|_| !path.is_dir()) {
+                    files.push((format!("{prefix}{stem}"), path));
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Returns an application directory and its subdirectories with the prefix of the desktop file
+/// IDs in each, visiting every directory once even when symlinks form a loop.
+pub(crate) fn directory_tree(app_dir: &Path) -> Vec<(PathBuf, String)> {
+    let mut visited = HashSet::new();
+    let mut tree = Vec::new();
+    let mut pending = vec![(app_dir.to_owned(), String::new())];
+    while let Some((dir, prefix)) = pending.pop() {
+        let Ok(canonical) = dir.canonicalize() else {
+            continue;
+        };
+        if !visited.insert(canonical) {
+            continue;
+        }
+        // Pushed in reverse so that subdirectories are visited in name order.
+        for path in dir_contents(&dir).into_iter().rev().filter(|p| p.is_dir()) {
+            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                let prefix = format!("{prefix}{name}-");
+                pending.push((path, prefix));
+            }
+        }
+        tree.push((dir, prefix));
+    }
+    tree
+}
+
+/// Returns the paths in a directory sorted by name, or nothing if it can't be read.
+fn dir_contents(dir: &Path) -> Vec<PathBuf> {
+    let Ok(contents) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = contents.map_while(Result::ok).map(|e| e.path()).collect();
+    paths.sort();
+    paths
+}
+
+fn parse_file(file: &DesktopFile, environment: &Environment) -> Result<DesktopEntry, Skip> {
+    let ini = load_source(&file.source).map_err(Skip::Ini)?;
+    let mut entry = DesktopEntry::from_ini(&file.id, &file.path, ini, environment)?;
+    entry.mtime = file.mtime;
+    Ok(entry)
+}
+
+/// Returns the modification time of a file, following symlinks.
+pub(crate) fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Loads the source of a desktop file, whose values are neither quoted nor escaped the way ini
+/// values can be.
+pub(crate) fn load_source(source: &str) -> Result<Ini, ini::ParseError> {
+    Ini::load_from_str_opt(
+        &normalize(source),
+        ini::ParseOption {
+            enabled_quote: false,
+            enabled_escape: false,
+        },
+    )
+}
+
+/// Strips a leading byte order mark and converts CRLF line endings, which some desktop files
+/// shipped by cross-platform applications have, so the group header and values parse as usual.
+fn normalize(source: &str) -> Cow<'_, str> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    match source.contains('\r') {
+        true => Cow::Owned(source.replace("\r\n", "\n")),
+        false => Cow::Borrowed(source),
+    }
+}
+
+/// Maps the items across a thread per available CPU, keeping the results in the input order.
+fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Worker thread should not panic"))
+            .collect()
+    })
+}
+
+/// Merges parsed files in search order, so the first successfully parsed file of an ID wins.
+/// Returns the path of each parsed file whose ID was already taken by a file earlier in the search
+/// order, with the path of that file.
+fn shadowed_files<'a>(
+    files: &'a [DesktopFile],
+    parsed: &[Result<DesktopEntry, Skip>],
+) -> Vec<(&'a Path, &'a Path)> {
+    let mut first: HashMap<&str, &Path> = HashMap::new();
+    let mut shadowed = Vec::new();
+    for (file, _) in files.iter().zip(parsed).filter(|(_, entry)| entry.is_ok()) {
+        match first.get(file.id.as_str()) {
+            Some(path) => shadowed.push((file.path.as_path(), *path)),
+            None => {
+                first.insert(&file.id, &file.path);
+            }
+        }
+    }
+    shadowed
+}
+
+fn merge_entries(
+    files: Vec<DesktopFile>,
+    parsed: Vec<Result<DesktopEntry, Skip>>,
+    dedup_by: &DedupBy,
+) -> BTreeMap<String, DesktopEntry> {
+    let mut ids = HashSet::new();
+    let mut keys = HashSet::new();
+    let mut entries = BTreeMap::new();
+    for (file, entry) in files.into_iter().zip(parsed) {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        // A file shadows the files with the same ID later in the search order, even when it is
+        // collapsed into another entry.
+        if !ids.insert(file.id.clone()) {
+            continue;
+        }
+        let key = match dedup_by {
+            DedupBy::Stem => None,
+            DedupBy::Name => Some(entry.name.clone()),
+            DedupBy::Exec => entry.program(),
+        };
+        if let Some(key) = key.filter(|_| !entry.hide()) {
+            if !keys.insert(key) {
+                continue;
+            }
+        }
+        entries.insert(file.id, entry);
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn dirs(data_home: Option<&str>, home: Option<&str>, data_dirs: Option<&str>) -> Vec<PathBuf> {
+        application_dirs_from(
+            data_home.map(OsString::from),
+            home.map(OsString::from),
+            data_dirs.map(OsString::from),
+        )
+    }
+
+    #[test]
+    fn application_dirs_defaults() {
+        assert_eq!(
+            dirs(None, Some("/home/me"), None),
+            [
+                PathBuf::from("/home/me/.local/share/applications"),
+                PathBuf::from("/usr/local/share/applications"),
+                PathBuf::from("/usr/share/applications"),
+            ]
+        );
+        assert_eq!(dirs(Some(""), None, Some("")).len(), 2);
+    }
+
+    #[test]
+    fn application_dirs_without_home() {
+        let system = [
+            PathBuf::from("/usr/local/share/applications"),
+            PathBuf::from("/usr/share/applications"),
+        ];
+        assert_eq!(dirs(None, None, None), system);
+        assert_eq!(dirs(Some(""), Some(""), Some("")), system);
+        assert_eq!(dirs(None, Some("relative"), None), system);
+    }
+
+    #[test]
+    fn application_dirs_overridden() {
+        assert_eq!(
+            dirs(Some("/data"), Some("/home/me"), Some("/a:/b")),
+            [
+                PathBuf::from("/data/applications"),
+                PathBuf::from("/a/applications"),
+                PathBuf::from("/b/applications"),
+            ]
+        );
+    }
+
+    #[test]
+    fn application_dirs_from_options() {
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--data-home",
+            "/data",
+            "--data-dirs",
+            "/a:/b",
+            "--include-dir",
+            "/extra",
+            "--prepend-dirs",
+        ]);
+        assert_eq!(
+            application_dirs(&cli),
+            [
+                PathBuf::from("/extra"),
+                PathBuf::from("/data/applications"),
+                PathBuf::from("/a/applications"),
+                PathBuf::from("/b/applications"),
+            ]
+        );
+    }
+
+    #[test]
+    fn include_dir_globs() {
+        let root = temp_dir("include-globs");
+        for dir in [
+            "a/applications",
+            "b/applications",
+            "c",
+            ".hidden/applications",
+        ] {
+            fs::create_dir_all(root.join("projects").join(dir)).unwrap();
+        }
+        fs::write(root.join("projects/file"), "").unwrap();
+        let expanded = |pattern: &str| expand_include_dir(&root.join(pattern), false);
+        assert_eq!(
+            expanded("projects/*/applications"),
+            [
+                root.join("projects/a/applications"),
+                root.join("projects/b/applications")
+            ]
+        );
+        assert_eq!(
+            expanded("projects/?"),
+            [
+                root.join("projects/a"),
+                root.join("projects/b"),
+                root.join("projects/c")
+            ]
+        );
+        assert_eq!(
+            expanded("projects/.*/applications"),
+            [root.join("projects/.hidden/applications")]
+        );
+        assert!(expanded("projects/*/missing").is_empty());
+        assert_eq!(expanded("missing"), [root.join("missing")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn application_dirs_deduplicated() {
+        assert_eq!(
+            dirs(Some("/usr/share"), None, Some("/a:/usr/share:/a")),
+            [
+                PathBuf::from("/usr/share/applications"),
+                PathBuf::from("/a/applications"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_dirs_are_marked() {
+        let root = temp_dir("describe");
+        let app_dirs = [root.clone(), root.join("missing")];
+        assert_eq!(
+            describe_dirs(&app_dirs),
+            format!("{0}\n{0}/missing (missing)\n", root.display())
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn nested_desktop_file_ids() {
+        let root = temp_dir("nested");
+        let app_dirs = [root.join("user"), root.join("system")];
+        fs::create_dir_all(app_dirs[0].join("kde")).unwrap();
+        fs::create_dir_all(app_dirs[1].join("kde/games")).unwrap();
+        let contents =
+            |exec: &str| format!("[Desktop Entry]\nType=Application\nName=App\nExec={exec}\n");
+        fs::write(app_dirs[0].join("kde-foo.desktop"), contents("user")).unwrap();
+        fs::write(app_dirs[1].join("kde/foo.desktop"), contents("system")).unwrap();
+        fs::write(app_dirs[1].join("kde/games/bar.desktop"), contents("bar")).unwrap();
+        fs::create_dir_all(app_dirs[1].join("dir.desktop")).unwrap();
+        std::os::unix::fs::symlink("..", app_dirs[1].join("kde/loop")).unwrap();
+        std::os::unix::fs::symlink(&app_dirs[1], app_dirs[0].join("kde/system")).unwrap();
+
+        let ids: Vec<String> = desktop_files(&app_dirs)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                "kde-foo",
+                "kde-system-kde-foo",
+                "kde-system-kde-games-bar",
+                "kde-foo",
+                "kde-games-bar"
+            ]
+        );
+        let entries = read_entries(
+            &app_dirs,
+            &Environment::default(),
+            &DedupBy::Stem,
+            false,
+            false,
+        );
+        assert_eq!(entries["kde-foo"].exec, "user");
+        assert_eq!(entries["kde-games-bar"].filename, "kde-games-bar");
+        assert!(entries["kde-foo"].mtime.is_some());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn byte_order_mark_and_crlf() {
+        let app_dirs = [temp_dir("crlf")];
+        fs::create_dir_all(&app_dirs[0]).unwrap();
+        fs::write(
+            app_dirs[0].join("windows.desktop"),
+            "\u{feff}[Desktop Entry]\r\nType=Application\r\nName=Windows\r\nExec=app %f\r\n",
+        )
+        .unwrap();
+
+        let entries = read_entries(
+            &app_dirs,
+            &Environment::default(),
+            &DedupBy::Stem,
+            false,
+            false,
+        );
+        let entry = &entries["windows"];
+        assert_eq!(entry.name, "Windows");
+        assert_eq!(entry.exec, "app %f");
+        fs::remove_dir_all(&app_dirs[0]).unwrap();
+    }
+
+    #[test]
+    fn parallel_parsing_matches_serial() {
+        let root = temp_dir("parallel");
+        let app_dirs = [root.join("user"), root.join("system"), root.join("missing")];
+        for (i, dir) in app_dirs[..2].iter().enumerate() {
+            fs::create_dir_all(dir).unwrap();
+            for n in 0..50 {
+                let contents =
+                    format!("[Desktop Entry]\nType=Application\nName=App {n}\nExec=app{i}\n");
+                fs::write(dir.join(format!("app{n}.desktop")), contents).unwrap();
+            }
+            fs::write(
+                dir.join(format!("only{i}.desktop")),
+                "[Desktop Entry]\nType=Application\nName=Only\nExec=only\n",
+            )
+            .unwrap();
+            fs::write(dir.join("readme.txt"), "not a desktop file").unwrap();
+        }
+        fs::write(
+            app_dirs[0].join("link.desktop"),
+            "[Desktop Entry]\nType=Link\nName=Link\n",
+        )
+        .unwrap();
+        fs::write(
+            app_dirs[1].join("link.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Link\nExec=link\n",
+        )
+        .unwrap();
+
+        let environment = Environment::default();
+        let entries = read_entries(&app_dirs, &environment, &DedupBy::Stem, false, false);
+
+        let files = read_desktop_files(&app_dirs, false);
+        let serial = files
+            .iter()
+            .map(|file| parse_file(file, &environment))
+            .collect();
+        assert_eq!(entries, merge_entries(files, serial, &DedupBy::Stem));
+        assert_eq!(entries.len(), 53);
+        assert_eq!(entries["app7"].exec, "app0");
+        assert_eq!(entries["link"].exec, "link");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn dedup_keys() {
+        let file = |stem: &str, keys: &str| DesktopFile {
+            id: stem.to_owned(),
+            path: PathBuf::from(format!("/apps/{stem}.desktop")),
+            source: format!("[Desktop Entry]\nType=Application\n{keys}"),
+            mtime: None,
+        };
+        let files = || {
+            vec![
+                file("code", "Name=Code\nExec=/usr/bin/code %F\n"),
+                file("code-url-handler", "Name=Code\nExec=code --open-url %U\n"),
+                file("com.visualstudio.code", "Name=VS Code\nExec=code\n"),
+                file("hidden", "Name=Code\nExec=code\nNoDisplay=true\n"),
+                file("code", "Name=Other\nExec=other\n"),
+            ]
+        };
+        let stems = |dedup_by| {
+            let files = files();
+            let environment = Environment::default();
+            let parsed = files.iter().map(|f| parse_file(f, &environment)).collect();
+            merge_entries(files, parsed, &dedup_by)
+                .into_keys()
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            stems(DedupBy::Stem),
+            [
+                "code",
+                "code-url-handler",
+                "com.visualstudio.code",
+                "hidden"
+            ]
+        );
+        assert_eq!(
+            stems(DedupBy::Name),
+            ["code", "com.visualstudio.code", "hidden"]
+        );
+        assert_eq!(stems(DedupBy::Exec), ["code", "hidden"]);
+    }
+
+    #[test]
+    fn shadowed_files_are_paired_with_the_first() {
+        let file = |dir: &str, id: &str, keys: &str| DesktopFile {
+            id: id.to_owned(),
+            path: PathBuf::from(format!("/{dir}/{id}.desktop")),
+            source: format!("[Desktop Entry]\nType=Application\n{keys}"),
+            mtime: None,
+        };
+        let files = [
+            file("home", "broken", "Name=Broken\n"),
+            file("home", "foot", "Name=Foot\nExec=foot\n"),
+            file("usr", "broken", "Name=Broken\nExec=broken\n"),
+            file("usr", "foot", "Name=Foot\nExec=foot\n"),
+            file("usr", "htop", "Name=Htop\nExec=htop\n"),
+        ];
+        let environment = Environment::default();
+        let parsed: Vec<_> = files.iter().map(|f| parse_file(f, &environment)).collect();
+        assert_eq!(
+            shadowed_files(&files, &parsed),
+            [(
+                Path::new("/usr/foot.desktop"),
+                Path::new("/home/foot.desktop")
+            )]
+        );
+    }
+}
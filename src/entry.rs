@@ -0,0 +1,874 @@
+//! Desktop entries and the parts of the environment that affect how they are parsed.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use std::{env, fmt, fs};
+
+use ini::{Ini, Properties};
+
+use crate::exec::{parse_exec, split_assignments};
+use crate::EntryType;
+
+/// The parts of the user's environment that affect how entries are parsed.
+#[derive(Debug, Default)]
+pub(crate) struct Environment {
+    pub(crate) desktops: Vec<String>,
+    /// Locale suffixes for localized keys, most specific first.
+    pub(crate) locales: Vec<String>,
+    /// Where TryExec programs are searched for.
+    pub(crate) search_path: SearchPath,
+    /// Whether Type=Link entries are parsed rather than skipped.
+    pub(crate) include_links: bool,
+}
+
+impl Environment {
+    pub(crate) fn new(desktop: Option<&str>) -> Environment {
+        Environment::from_vars(desktop, |var| env::var(var).ok())
+    }
+
+    /// Builds the environment with the variables returned by `var`, where `desktop` is the one
+    /// given to --desktop.
+    fn from_vars(desktop: Option<&str>, var: impl Fn(&str) -> Option<String>) -> Environment {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| var(name))
+            .find(|locale| !locale.is_empty())
+            .unwrap_or_default();
+        let desktops = match desktop {
+            Some(desktop) => desktop.to_owned(),
+            None => current_desktop(var),
+        };
+        Environment {
+            desktops: desktops
+                .split(':')
+                .filter(|d| !d.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            locales: locale_variants(&locale),
+            search_path: SearchPath::from_env(),
+            include_links: false,
+        }
+    }
+
+    /// Looks up the value of `key` for the most specific matching locale, falling back to the
+    /// unlocalized key.
+    pub(crate) fn localized<'a>(&self, section: &'a Properties, key: &str) -> Option<&'a str> {
+        self.locales
+            .iter()
+            .find_map(|locale| section.get(format!("{key}[{locale}]")))
+            .or_else(|| section.get(key))
+    }
+
+    /// Whether the program of a TryExec key is installed. A relative path such as `./launch` is
+    /// resolved against the Path key, where the entry is launched, and a program name is looked up
+    /// in $PATH, as it would be when spawned. Neither is looked up in the working directory.
+    pub(crate) fn finds(&self, try_exec: &str, dir: Option<&Path>) -> bool {
+        let program = Path::new(try_exec);
+        match dir {
+            _ if program.is_absolute() => program.exists(),
+            Some(dir) if try_exec.contains('/') => dir.join(program).exists(),
+            _ => self.search_path.contains(try_exec),
+        }
+    }
+
+    /// Whether an entry with the given OnlyShowIn and NotShowIn values should be shown.
+    pub(crate) fn shows(&self, only_show_in: Option<&str>, not_show_in: Option<&str>) -> bool {
+        let matches = |list: &str| parse_list(list).iter().any(|d| self.desktops.contains(d));
+        only_show_in.is_none_or(matches) && !not_show_in.is_some_and(matches)
+    }
+}
+
+/// The directories of `$PATH` and, once a program is looked up, the names of the files in them, so
+/// that looking up the TryExec of every entry doesn't search every directory.
+#[derive(Debug)]
+pub(crate) struct SearchPath {
+    dirs: Vec<PathBuf>,
+    names: OnceLock<HashSet<OsString>>,
+}
+
+impl Default for SearchPath {
+    fn default() -> SearchPath {
+        SearchPath::from_env()
+    }
+}
+
+impl SearchPath {
+    pub(crate) fn from_env() -> SearchPath {
+        // A missing $PATH is an empty search path rather than an error.
+        let path_var = env::var_os("PATH").unwrap_or_default();
+        SearchPath::new(env::split_paths(&path_var).collect())
+    }
+
+    fn new(dirs: Vec<PathBuf>) -> SearchPath {
+        SearchPath {
+            dirs: dirs
+                .into_iter()
+                .filter(|d| !d.as_os_str().is_empty())
+                .collect(),
+            names: OnceLock::new(),
+        }
+    }
+
+    /// Whether `program` exists relative to one of the directories.
+    pub(crate) fn contains(&self, program: &str) -> bool {
+        if program.is_empty() || program.contains('/') {
+            return self.dirs.iter().any(|d| d.join(program).exists());
+        }
+        let names = self.names.get_or_init(|| {
+            let files = self
+                .dirs
+                .iter()
+                .filter_map(|d| fs::read_dir(d).ok())
+                .flatten();
+            files
+                .filter_map(Result::ok)
+                // Only symbolic links need to be followed to know whether their target exists.
+                .filter(|f| !f.file_type().is_ok_and(|t| t.is_symlink()) || f.path().exists())
+                .map(|f| f.file_name())
+                .collect()
+        });
+        names.contains(OsStr::new(program))
+    }
+}
+
+/// Splits the value of a list key on the semicolons not escaped as `\;`, dropping empty elements
+/// such as the one after the trailing semicolon.
+pub(crate) fn parse_list(value: &str) -> Vec<String> {
+    let mut list = Vec::new();
+    let mut element = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&';') => {
+                chars.next();
+                element.push(';');
+            }
+            ';' => list.push(std::mem::take(&mut element)),
+            c => element.push(c),
+        }
+    }
+    list.push(element);
+    list.retain(|e| !e.is_empty());
+    list
+}
+
+/// Replaces the escape sequences of a string value, `\s`, `\n`, `\t`, `\r` and `\\`, keeping
+/// other backslashes as they are.
+pub(crate) fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('s')) => ' ',
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('t')) => '\t',
+            ('\\', Some('r')) => '\r',
+            ('\\', Some('\\')) => '\\',
+            (c, _) => {
+                unescaped.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        unescaped.push(escaped);
+    }
+    unescaped
+}
+
+/// Variables set by compositors that leave $XDG_CURRENT_DESKTOP unset, with the desktop name they
+/// imply. Sway also sets $I3SOCK, so it comes before i3.
+const COMPOSITOR_VARS: [(&str, &str); 5] = [
+    ("SWAYSOCK", "sway"),
+    ("HYPRLAND_INSTANCE_SIGNATURE", "Hyprland"),
+    ("NIRI_SOCKET", "niri"),
+    ("WAYFIRE_SOCKET", "Wayfire"),
+    ("I3SOCK", "i3"),
+];
+
+/// Returns $XDG_CURRENT_DESKTOP, or when it is unset or empty the desktop name inferred from the
+/// session name of the display manager or the variables of a compositor. Without either, such as
+/// on bare river, the desktop is unknown: entries with OnlyShowIn are hidden and NotShowIn has no
+/// effect. $WAYLAND_DISPLAY and $DISPLAY only tell that there is a display, not which one.
+fn current_desktop(var: impl Fn(&str) -> Option<String>) -> String {
+    let var = |name: &str| var(name).filter(|v| !v.is_empty());
+    if let Some(desktops) = var("XDG_CURRENT_DESKTOP") {
+        return desktops;
+    }
+    // $DESKTOP_SESSION is sometimes the path of the session file rather than its name.
+    let session = var("XDG_SESSION_DESKTOP").or_else(|| var("DESKTOP_SESSION"));
+    if let Some(session) = session {
+        let name = session.rsplit('/').next().unwrap_or_default();
+        let name = name.strip_suffix(".desktop").unwrap_or(name);
+        return registered_desktop(name).unwrap_or(name).to_owned();
+    }
+    COMPOSITOR_VARS
+        .iter()
+        .find(|(name, _)| var(name).is_some())
+        .map(|(_, desktop)| desktop.to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the name desktop entries use for a session name, which is usually lowercase.
+fn registered_desktop(session: &str) -> Option<&'static str> {
+    let desktop = match session.to_lowercase().as_str() {
+        "gnome" | "gnome-xorg" | "gnome-wayland" | "ubuntu" => "GNOME",
+        "plasma" | "plasmawayland" | "plasmax11" | "kde" | "kde-plasma" => "KDE",
+        "xfce" | "xfce4" => "XFCE",
+        "lxqt" => "LXQt",
+        "lxde" => "LXDE",
+        "mate" => "MATE",
+        "cinnamon" | "cinnamon-wayland" => "X-Cinnamon",
+        "budgie-desktop" | "budgie" => "Budgie",
+        "pantheon" => "Pantheon",
+        "hyprland" => "Hyprland",
+        "sway" => "sway",
+        _ => return None,
+    };
+    Some(desktop)
+}
+
+/// Returns the locale suffixes to try for a locale of the form `lang_COUNTRY.ENCODING@MODIFIER`,
+/// in the order given by the desktop entry specification.
+pub(crate) fn locale_variants(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut variants = Vec::new();
+    if let Some(country) = country {
+        if let Some(modifier) = modifier {
+            variants.push(format!("{lang}_{country}@{modifier}"));
+        }
+        variants.push(format!("{lang}_{country}"));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{lang}@{modifier}"));
+    }
+    variants.push(lang.to_owned());
+    variants
+}
+
+/// Why a desktop file was skipped.
+#[derive(Debug)]
+pub(crate) enum Skip {
+    Ini(ini::ParseError),
+    NoSection,
+    Type(Option<String>),
+    Missing(&'static str),
+    Empty(&'static str),
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Skip::Ini(e) => write!(f, "{}", e),
+            Skip::NoSection => write!(f, "no [Desktop Entry] group"),
+            Skip::Type(Some(t)) => write!(f, "Type is {} rather than Application", t),
+            Skip::Type(None) => write!(f, "no Type key"),
+            Skip::Missing(key) => write!(f, "no {} key", key),
+            Skip::Empty(key) => write!(f, "{} is empty", key),
+        }
+    }
+}
+
+/// Why an entry is hidden from the menu.
+#[derive(Debug, Hash, PartialEq, Eq)]
+pub(crate) enum HideReason {
+    Hidden,
+    NoDisplay,
+    NotShownIn,
+    TryExec(String),
+    Blocked,
+    Forced,
+}
+
+impl fmt::Display for HideReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HideReason::Hidden => write!(f, "Hidden is true"),
+            HideReason::NoDisplay => write!(f, "NoDisplay is true"),
+            HideReason::NotShownIn => write!(f, "not shown in the current desktop"),
+            HideReason::TryExec(try_exec) => write!(f, "TryExec {} was not found", try_exec),
+            HideReason::Blocked => write!(f, "listed in the blocklist"),
+            HideReason::Forced => write!(f, "hidden with --force-hide"),
+        }
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq)]
+pub(crate) struct DesktopAction {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) exec: String,
+}
+
+#[derive(Debug, Hash, PartialEq, Eq)]
+pub(crate) struct DesktopEntry {
+    pub(crate) name: String,
+    pub(crate) filename: String,
+    pub(crate) exec: String,
+    pub(crate) hidden: Option<HideReason>,
+    pub(crate) terminal: bool,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) generic_name: Option<String>,
+    pub(crate) comment: Option<String>,
+    pub(crate) categories: Vec<String>,
+    pub(crate) keywords: Vec<String>,
+    pub(crate) mime_types: Vec<String>,
+    /// The interfaces of the Implements key, such as org.freedesktop.FileManager1.
+    pub(crate) implements: Vec<String>,
+    pub(crate) startup_notify: bool,
+    pub(crate) startup_wm_class: Option<String>,
+    pub(crate) dbus_activatable: bool,
+    pub(crate) icon: Option<String>,
+    pub(crate) location: PathBuf,
+    pub(crate) actions: Vec<DesktopAction>,
+    /// Variables set for the launched command, from keys such as `X-DmenuDesktop-Env-GDK_BACKEND`.
+    pub(crate) environment: Vec<(String, String)>,
+    pub(crate) kind: EntryKind,
+    /// When the desktop file was last modified, set when it is read from disk.
+    pub(crate) mtime: Option<SystemTime>,
+}
+
+/// What selecting an entry does.
+#[derive(Debug, Hash, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    /// Runs the Exec key.
+    Application,
+    /// Opens the URL of a Type=Link entry, whose Exec key is empty.
+    Link(String),
+}
+
+/// Prefix of the vendor keys whose value is set as the environment variable named by the rest of
+/// the key when the entry is launched.
+const ENV_KEY_PREFIX: &str = "X-DmenuDesktop-Env-";
+
+impl DesktopEntry {
+    pub(crate) fn from_ini(
+        filename: &str,
+        location: &Path,
+        ini: Ini,
+        environment: &Environment,
+    ) -> Result<DesktopEntry, Skip> {
+        let section = ini.section(Some("Desktop Entry")).ok_or(Skip::NoSection)?;
+        let is_link = match section.get("Type") {
+            Some("Application") => false,
+            Some("Link") if environment.include_links => true,
+            other => return Err(Skip::Type(other.map(str::to_owned))),
+        };
+
+        let name = environment
+            .localized(section, "Name")
+            .ok_or(Skip::Missing("Name"))?;
+        let (exec, kind) = match is_link {
+            true => {
+                let url = section.get("URL").ok_or(Skip::Missing("URL"))?.trim();
+                if url.is_empty() {
+                    return Err(Skip::Empty("URL"));
+                }
+                (String::new(), EntryKind::Link(url.to_owned()))
+            }
+            false => {
+                let exec = unescape(section.get("Exec").ok_or(Skip::Missing("Exec"))?);
+                if exec.trim().is_empty() {
+                    return Err(Skip::Empty("Exec"));
+                }
+                (exec, EntryKind::Application)
+            }
+        };
+
+        let generic_name = environment
+            .localized(section, "GenericName")
+            .map(str::to_owned);
+        let comment = environment.localized(section, "Comment").map(str::to_owned);
+        let categories = parse_list(section.get("Categories").unwrap_or_default());
+        let keywords = parse_list(
+            environment
+                .localized(section, "Keywords")
+                .unwrap_or_default(),
+        );
+        let mime_types = parse_list(section.get("MimeType").unwrap_or_default());
+        let implements = parse_list(section.get("Implements").unwrap_or_default());
+        let try_exec = section.get("TryExec");
+        let path = section.get("Path").map(PathBuf::from);
+        let icon = section.get("Icon").map(str::to_owned);
+        let terminal = section.get("Terminal") == Some("true");
+        let startup_notify = section.get("StartupNotify") == Some("true");
+        let startup_wm_class = section.get("StartupWMClass").map(str::to_owned);
+        let dbus_activatable = section.get("DBusActivatable") == Some("true");
+
+        let exec_exists =
+            |try_exec: Option<&str>| try_exec.is_none_or(|t| environment.finds(t, path.as_deref()));
+
+        let variables = section
+            .iter()
+            .filter_map(|(key, value)| {
+                let name = key.strip_prefix(ENV_KEY_PREFIX).filter(|n| !n.is_empty())?;
+                Some((name.to_owned(), value.to_owned()))
+            })
+            .collect();
+
+        let actions = parse_list(section.get("Actions").unwrap_or_default())
+            .into_iter()
+            .filter_map(|id| {
+                let section = ini.section(Some(format!("Desktop Action {id}")))?;
+                Some(DesktopAction {
+                    id,
+                    name: environment.localized(section, "Name")?.to_owned(),
+                    exec: unescape(section.get("Exec").filter(|e| !e.trim().is_empty())?),
+                })
+            })
+            .collect();
+
+        let hidden = if section.get("Hidden") == Some("true") {
+            Some(HideReason::Hidden)
+        } else if section.get("NoDisplay") == Some("true") {
+            Some(HideReason::NoDisplay)
+        } else if !environment.shows(section.get("OnlyShowIn"), section.get("NotShowIn")) {
+            Some(HideReason::NotShownIn)
+        } else if !exec_exists(try_exec) {
+            try_exec.map(|t| HideReason::TryExec(t.to_owned()))
+        } else {
+            None
+        };
+
+        Ok(DesktopEntry {
+            name: name.trim().to_owned(),
+            filename: filename.to_owned(),
+            exec,
+            hidden,
+            terminal,
+            path,
+            generic_name,
+            comment,
+            categories,
+            keywords,
+            mime_types,
+            implements,
+            startup_notify,
+            startup_wm_class,
+            dbus_activatable,
+            icon,
+            location: location.to_owned(),
+            actions,
+            environment: variables,
+            kind,
+            mtime: None,
+        })
+    }
+
+    pub(crate) fn hide(&self) -> bool {
+        self.hidden.is_some()
+    }
+
+    /// Returns the name, the filename, or the program of the Exec key falling back to the name.
+    pub(crate) fn field(&self, entry_type: &EntryType) -> Cow<'_, str> {
+        match entry_type {
+            EntryType::Name => Cow::Borrowed(&self.name),
+            EntryType::Filename => Cow::Borrowed(&self.filename),
+            EntryType::Command => match self.command() {
+                Some(command) => Cow::Owned(command),
+                None => Cow::Borrowed(&self.name),
+            },
+        }
+    }
+
+    /// Returns the program run by the Exec key as it is written, unquoted.
+    fn command(&self) -> Option<String> {
+        let (_, argv) = split_assignments(parse_exec(&self.exec)?);
+        argv.into_iter().next()
+    }
+
+    /// Returns the first of the Categories, which --group-by-category groups the entry under.
+    pub(crate) fn primary_category(&self) -> &str {
+        self.categories.first().map_or("Other", String::as_str)
+    }
+
+    /// Returns the file name of the program run by the Exec key.
+    pub(crate) fn program(&self) -> Option<String> {
+        let command = self.command()?;
+        let program = Path::new(&command).file_name()?;
+        Some(program.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{parse, temp_dir};
+
+    fn name_in(locale: &str) -> String {
+        let environment = Environment {
+            locales: locale_variants(locale),
+            ..Default::default()
+        };
+        let contents = "[Desktop Entry]\nType=Application\nExec=app\n\
+            Name=Files\nName[de]=Dateien\nName[de_DE]=Dateien (DE)\nName[de_DE@euro]=Dateien (Euro)\n";
+        parse(contents, &environment).unwrap().name
+    }
+
+    #[test]
+    fn localized_name() {
+        assert_eq!(name_in("de_DE.UTF-8@euro"), "Dateien (Euro)");
+        assert_eq!(name_in("de_DE.UTF-8"), "Dateien (DE)");
+        assert_eq!(name_in("de_AT.UTF-8"), "Dateien");
+        assert_eq!(name_in("de"), "Dateien");
+        assert_eq!(name_in("fr_FR.UTF-8"), "Files");
+        assert_eq!(name_in("C"), "Files");
+    }
+
+    #[test]
+    fn locale_variant_order() {
+        assert_eq!(
+            locale_variants("sr_YU.UTF-8@Latn"),
+            ["sr_YU@Latn", "sr_YU", "sr@Latn", "sr"]
+        );
+        assert!(locale_variants("").is_empty());
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(parse_list("a;b;"), ["a", "b"]);
+        assert_eq!(parse_list("a\\;b;c"), ["a;b", "c"]);
+        assert_eq!(unescape("a\\sb\\\\\\$c\\"), "a b\\\\$c\\");
+        assert_eq!(parse_list("single"), ["single"]);
+        assert_eq!(parse_list(";;a;;b\\n"), ["a", "b\\n"]);
+        assert!(parse_list("").is_empty());
+    }
+
+    #[test]
+    fn categories() {
+        let contents = "[Desktop Entry]\nType=Application\nName=App\nExec=app\n\
+            Categories=Network;WebBrowser;\n";
+        let entry = parse(contents, &Environment::default()).unwrap();
+        assert_eq!(entry.categories, ["Network", "WebBrowser"]);
+    }
+
+    #[test]
+    fn implements() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Files\nExec=files\n\
+            Implements=org.freedesktop.FileManager1;org.gnome.Shell.SearchProvider2;\n";
+        let entry = parse(contents, &Environment::default()).unwrap();
+        assert_eq!(
+            entry.implements,
+            [
+                "org.freedesktop.FileManager1",
+                "org.gnome.Shell.SearchProvider2"
+            ]
+        );
+    }
+
+    #[test]
+    fn absolute_try_exec_is_not_searched_for() {
+        let contents = "[Desktop Entry]\nType=Application\nName=App\nExec=sh\n\
+            TryExec=/nonexistent/sh\n";
+        assert!(parse(contents, &Environment::default()).unwrap().hide());
+    }
+
+    #[test]
+    fn skip_and_hide_reasons() {
+        let reason = |contents: &str| match parse(contents, &Environment::default()) {
+            Ok(entry) => entry.hidden.map(|h| h.to_string()).unwrap_or_default(),
+            Err(skip) => skip.to_string(),
+        };
+        assert_eq!(reason("[Other]\nName=App\n"), "no [Desktop Entry] group");
+        assert_eq!(
+            reason("[Desktop Entry]\nType=Link\nName=App\n"),
+            "Type is Link rather than Application"
+        );
+        assert_eq!(reason("[Desktop Entry]\nName=App\n"), "no Type key");
+        assert_eq!(
+            reason("[Desktop Entry]\nType=Application\nName=App\n"),
+            "no Exec key"
+        );
+        assert_eq!(
+            reason("[Desktop Entry]\nType=Application\nName=App\nExec=app\nNoDisplay=true\n"),
+            "NoDisplay is true"
+        );
+        assert_eq!(
+            reason("[Desktop Entry]\nType=Application\nName=App\nExec=app\nTryExec=/nonexistent\n"),
+            "TryExec /nonexistent was not found"
+        );
+        assert_eq!(
+            reason("[Desktop Entry]\nType=Application\nName=App\nExec=app\n"),
+            ""
+        );
+    }
+
+    fn app(keys: &str) -> DesktopEntry {
+        let contents = format!("[Desktop Entry]\nType=Application\nName=App\n{keys}\n");
+        parse(&contents, &Environment::default()).unwrap()
+    }
+
+    #[test]
+    fn wrong_or_missing_type() {
+        for keys in ["", "Type=Link\n", "Type=Directory\n", "Type=application\n"] {
+            let contents = format!("[Desktop Entry]\n{keys}Name=App\nExec=app\n");
+            assert!(matches!(
+                parse(&contents, &Environment::default()),
+                Err(Skip::Type(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn no_display_and_hidden() {
+        assert!(!app("Exec=app").hide());
+        assert!(app("Exec=app\nNoDisplay=true").hide());
+        assert!(!app("Exec=app\nNoDisplay=false").hide());
+        assert_eq!(
+            app("Exec=app\nHidden=true").hidden,
+            Some(HideReason::Hidden)
+        );
+        assert!(!app("Exec=app\nHidden=false").hide());
+    }
+
+    #[test]
+    fn search_path_lookups() {
+        let dir = temp_dir("search-path");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("app"), "").unwrap();
+        fs::write(dir.join("sub/tool"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("broken")).unwrap();
+        let search_path = SearchPath::new(vec![PathBuf::new(), dir.clone()]);
+        assert!(search_path.contains("app"));
+        assert!(search_path.contains("sub/tool"));
+        assert!(!search_path.contains("tool"));
+        assert!(!search_path.contains("broken"));
+        assert!(!search_path.contains("missing"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn try_exec() {
+        assert!(!app("Exec=sh\nTryExec=/bin/sh").hide());
+        assert!(app("Exec=sh\nTryExec=/nonexistent/sh").hide());
+        assert!(!app("Exec=sh\nTryExec=sh").hide());
+        let missing = "dmenu-desktop-missing-program";
+        assert!(app(&format!("Exec={missing}\nTryExec={missing}")).hide());
+    }
+
+    #[test]
+    fn try_exec_is_searched_for_in_path() {
+        let dir = temp_dir("try-exec");
+        fs::write(dir.join("app"), "").unwrap();
+        let environment = Environment {
+            search_path: SearchPath::new(vec![dir.clone()]),
+            ..Default::default()
+        };
+        let hidden = |keys: &str| {
+            let contents = format!("[Desktop Entry]\nType=Application\nName=App\n{keys}\n");
+            parse(&contents, &environment).unwrap().hidden
+        };
+        assert_eq!(hidden("Exec=launcher app\nTryExec=app"), None);
+        assert_eq!(
+            hidden("Exec=app\nTryExec=tool"),
+            Some(HideReason::TryExec("tool".to_owned()))
+        );
+        let absolute = dir.join("tool").display().to_string();
+        assert!(hidden(&format!("Exec=app\nTryExec={absolute}")).is_some());
+        fs::write(dir.join("tool"), "").unwrap();
+        assert_eq!(hidden(&format!("Exec=app\nTryExec={absolute}")), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn try_exec_is_relative_to_path() {
+        let dir = temp_dir("try-exec-path");
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::write(dir.join("launch"), "").unwrap();
+        fs::write(dir.join("bin/tool"), "").unwrap();
+        let hidden = |keys: &str| {
+            let contents = format!(
+                "[Desktop Entry]\nType=Application\nName=App\nPath={}\n{keys}\n",
+                dir.display()
+            );
+            parse(&contents, &Environment::default()).unwrap().hidden
+        };
+        assert_eq!(hidden("Exec=./launch\nTryExec=./launch"), None);
+        assert_eq!(hidden("Exec=bin/tool\nTryExec=bin/tool"), None);
+        assert!(hidden("Exec=./missing\nTryExec=./missing").is_some());
+        // A program name is still looked up in $PATH, where it would be spawned from.
+        assert!(hidden("Exec=launch\nTryExec=launch").is_some());
+        let without_path =
+            "[Desktop Entry]\nType=Application\nName=App\nExec=x\nTryExec=./launch\n";
+        assert!(parse(without_path, &Environment::default()).unwrap().hide());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn environment_keys() {
+        let entry = app(
+            "Exec=app\nX-DmenuDesktop-Env-GDK_BACKEND=x11\nX-DmenuDesktop-Env-=a\n\
+            X-Other-Env-FOO=bar\nX-DmenuDesktop-Env-QT_SCALE_FACTOR=2",
+        );
+        assert_eq!(
+            entry.environment,
+            [
+                ("GDK_BACKEND".to_owned(), "x11".to_owned()),
+                ("QT_SCALE_FACTOR".to_owned(), "2".to_owned())
+            ]
+        );
+        assert!(app("Exec=app").environment.is_empty());
+    }
+
+    #[test]
+    fn links() {
+        let contents = "[Desktop Entry]\nType=Link\nName=Docs\nURL=https://example.com/docs\n";
+        let mut environment = Environment::default();
+        assert_eq!(
+            parse(contents, &environment).unwrap_err().to_string(),
+            "Type is Link rather than Application"
+        );
+        environment.include_links = true;
+        let entry = parse(contents, &environment).unwrap();
+        assert_eq!(
+            entry.kind,
+            EntryKind::Link("https://example.com/docs".to_owned())
+        );
+        assert_eq!(entry.exec, "");
+        let contents = "[Desktop Entry]\nType=Link\nName=Docs\nURL= \n";
+        assert_eq!(
+            parse(contents, &environment).unwrap_err().to_string(),
+            "URL is empty"
+        );
+        assert_eq!(app("Exec=app").kind, EntryKind::Application);
+    }
+
+    #[test]
+    fn command_field() {
+        let command = |exec: &str| {
+            app(&format!("Exec={exec}"))
+                .field(&EntryType::Command)
+                .into_owned()
+        };
+        assert_eq!(command("\"/opt/My App/bin/app\" %U"), "/opt/My App/bin/app");
+        assert_eq!(command("firefox %u"), "firefox");
+        assert_eq!(command("GDK_BACKEND=x11 /usr/bin/gimp %U"), "/usr/bin/gimp");
+        assert_eq!(command("\"unterminated"), "App");
+        let entry = app("Exec=\"/opt/My App/bin/app\" %U");
+        assert_eq!(entry.program().as_deref(), Some("app"));
+    }
+
+    #[test]
+    fn terminal() {
+        assert!(app("Exec=htop\nTerminal=true").terminal);
+        assert!(!app("Exec=htop\nTerminal=false").terminal);
+        assert!(!app("Exec=htop").terminal);
+    }
+
+    #[test]
+    fn working_directory() {
+        assert_eq!(
+            app("Exec=app\nPath=/tmp/work").path,
+            Some(PathBuf::from("/tmp/work"))
+        );
+        assert_eq!(app("Exec=app").path, None);
+    }
+
+    #[test]
+    fn empty_exec_is_skipped() {
+        for exec in ["Exec=", "Exec=   ", "Exec=\t"] {
+            let contents = format!("[Desktop Entry]\nType=Application\nName=App\n{exec}\n");
+            let skip = parse(&contents, &Environment::default()).unwrap_err();
+            assert!(matches!(skip, Skip::Empty("Exec")));
+        }
+    }
+
+    fn hidden_in(desktop: &str, keys: &str) -> bool {
+        let contents = format!("[Desktop Entry]\nType=Application\nName=App\nExec=app\n{keys}\n");
+        parse(&contents, &Environment::new(Some(desktop)))
+            .unwrap()
+            .hide()
+    }
+
+    #[test]
+    fn only_show_in() {
+        assert!(!hidden_in("GNOME", "OnlyShowIn=GNOME;KDE;"));
+        assert!(!hidden_in("ubuntu:GNOME", "OnlyShowIn=GNOME;"));
+        assert!(hidden_in("KDE", "OnlyShowIn=GNOME;"));
+        assert!(hidden_in("gnome", "OnlyShowIn=GNOME;"));
+        assert!(hidden_in("", "OnlyShowIn=GNOME;"));
+    }
+
+    #[test]
+    fn not_show_in() {
+        assert!(hidden_in("GNOME", "NotShowIn=GNOME;"));
+        assert!(hidden_in("ubuntu:GNOME", "NotShowIn=KDE;GNOME;"));
+        assert!(!hidden_in("KDE", "NotShowIn=GNOME;"));
+        assert!(!hidden_in("", "NotShowIn=GNOME;"));
+    }
+
+    #[test]
+    fn inferred_desktop() {
+        fn lookup<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+            |name| {
+                let value = vars.iter().find(|(var, _)| *var == name)?.1;
+                Some(value.to_owned())
+            }
+        }
+        let desktop = |vars: &[(&str, &str)]| current_desktop(lookup(vars));
+        let wayland = ("WAYLAND_DISPLAY", "wayland-1");
+        assert_eq!(
+            desktop(&[("XDG_CURRENT_DESKTOP", "ubuntu:GNOME")]),
+            "ubuntu:GNOME"
+        );
+        assert_eq!(
+            desktop(&[
+                ("XDG_CURRENT_DESKTOP", "sway"),
+                ("XDG_SESSION_DESKTOP", "gnome")
+            ]),
+            "sway"
+        );
+        assert_eq!(
+            desktop(&[
+                ("XDG_CURRENT_DESKTOP", ""),
+                ("XDG_SESSION_DESKTOP", "gnome")
+            ]),
+            "GNOME"
+        );
+        assert_eq!(
+            desktop(&[("DESKTOP_SESSION", "/usr/share/xsessions/plasma")]),
+            "KDE"
+        );
+        assert_eq!(desktop(&[("DESKTOP_SESSION", "river")]), "river");
+        assert_eq!(
+            desktop(&[
+                wayland,
+                ("SWAYSOCK", "/run/sway.sock"),
+                ("I3SOCK", "/run/sway.sock")
+            ]),
+            "sway"
+        );
+        assert_eq!(
+            desktop(&[("DISPLAY", ":0"), ("I3SOCK", "/run/i3.sock")]),
+            "i3"
+        );
+        assert_eq!(desktop(&[wayland]), "");
+        assert_eq!(desktop(&[]), "");
+
+        let environment = Environment::new(Some("XFCE:"));
+        assert_eq!(environment.desktops, ["XFCE"]);
+        let vars = &[
+            ("XDG_CURRENT_DESKTOP", "GNOME"),
+            ("SWAYSOCK", "/run/sway.sock"),
+        ];
+        let environment = Environment::from_vars(None, lookup(vars));
+        assert_eq!(environment.desktops, ["GNOME"]);
+        let environment = Environment::from_vars(Some("XFCE:"), lookup(vars));
+        assert_eq!(environment.desktops, ["XFCE"]);
+    }
+}
@@ -0,0 +1,181 @@
+//! The errors dmenu-desktop exits with and their exit codes: 0 once the selection is launched, 1
+//! for errors, including invalid options, 2 when nothing is selected and 3 when there are no
+//! entries to show.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Exit code when dmenu is closed without selecting anything.
+pub(crate) const EXIT_CANCELLED: u8 = 2;
+
+/// Exit code when no entry is left to show, such as when --filter matches none.
+pub(crate) const EXIT_NO_ENTRIES: u8 = 3;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The options can't be used together or have invalid values.
+    Usage(String),
+    InvalidDmenuCommand,
+    /// The menu could not be run.
+    Menu(io::Error),
+    /// Nothing was selected in the menu.
+    Cancelled,
+    /// No entry is listed.
+    NoEntries,
+    /// The selection matches no entry and --exec-unmatched is not given.
+    NoMatch(String),
+    /// No entry has the value given to --select.
+    NoSuchEntry(String),
+    /// Several entries have the value given to --select, listed by desktop file ID.
+    Ambiguous(String, Vec<String>),
+    /// The selection run with --exec-unmatched can't be split into a command.
+    InvalidCommand,
+    InvalidExec,
+    EmptyExec,
+    InvalidTerminal,
+    /// --terminal-prefix was used without --terminal and no terminal was found.
+    NoTerminal,
+    InvalidUrlOpener,
+    /// No process of the selected entry could be started.
+    SpawnFailed(io::Error),
+    /// A program to run is not installed, where `role` says what it is for.
+    NotFound {
+        role: &'static str,
+        program: String,
+    },
+    /// The files given to a program don't fit in the argument list the system accepts.
+    ArgumentsTooLong {
+        program: String,
+    },
+    /// The desktop file given to the validate subcommand would not be listed.
+    Invalid(PathBuf),
+    /// A command or the daemon failed, after reporting the failure itself. Commands run with
+    /// --exec-unmatched exit with 1 whatever their status, the daemon passes on its exit code.
+    Exited(u8),
+    Io(io::Error),
+}
+
+impl Error {
+    /// Turns the error of starting a program into `NotFound` when the program doesn't exist, into
+    /// `ArgumentsTooLong` when its arguments are over ARG_MAX, and into `other` otherwise.
+    pub(crate) fn spawning(
+        role: &'static str,
+        program: &OsStr,
+        e: io::Error,
+        other: fn(io::Error) -> Error,
+    ) -> Error {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound {
+                role,
+                program: program.to_string_lossy().into_owned(),
+            },
+            io::ErrorKind::ArgumentListTooLong => Error::ArgumentsTooLong {
+                program: program.to_string_lossy().into_owned(),
+            },
+            _ => other(e),
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Cancelled => EXIT_CANCELLED,
+            Error::NoEntries => EXIT_NO_ENTRIES,
+            Error::Exited(code) => *code,
+            _ => 1,
+        }
+    }
+
+    /// Prints the error to stderr, unless it needs no explanation or was already reported.
+    pub fn report(&self) {
+        if !matches!(self, Error::Cancelled | Error::Exited(_)) {
+            eprintln!("{}", self);
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::InvalidDmenuCommand => write!(f, "Invalid dmenu command."),
+            Error::Menu(e) => write!(f, "Could not run the menu: {}", e),
+            Error::Cancelled => write!(f, "Nothing was selected."),
+            Error::NoEntries => write!(f, "No entries to show."),
+            Error::NoMatch(selection) => write!(
+                f,
+                "No entry matches '{}', use --exec-unmatched to run it as a command.",
+                selection
+            ),
+            Error::NoSuchEntry(value) => write!(f, "No entry matches '{}'.", value),
+            Error::Ambiguous(value, ids) => {
+                write!(f, "Several entries match '{}': {}.", value, ids.join(", "))
+            }
+            Error::InvalidCommand => write!(f, "Invalid command."),
+            Error::InvalidExec => write!(f, "Invalid exec key."),
+            Error::EmptyExec => write!(f, "Empty exec key."),
+            Error::InvalidTerminal => write!(f, "Invalid terminal command."),
+            Error::NoTerminal => write!(f, "No terminal found, set one with --terminal."),
+            Error::InvalidUrlOpener => write!(f, "Invalid URL opener command."),
+            Error::SpawnFailed(e) => write!(f, "Could not launch the application: {}", e),
+            Error::NotFound { role, program } => {
+                write!(f, "{} '{}' not found in PATH.", role, program)
+            }
+            Error::ArgumentsTooLong { program } => write!(
+                f,
+                "Too many or too long files for '{}', the argument list is over the system limit.",
+                program
+            ),
+            Error::Invalid(path) => write!(f, "{} would not be listed.", path.display()),
+            Error::Exited(code) => write!(f, "Exited with status {}.", code),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Menu(e) | Error::SpawnFailed(e) | Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(Error::Cancelled.exit_code(), EXIT_CANCELLED);
+        assert_eq!(Error::NoEntries.exit_code(), EXIT_NO_ENTRIES);
+        assert_eq!(Error::Exited(127).exit_code(), 127);
+        assert_eq!(Error::InvalidExec.exit_code(), 1);
+        let spawn = Error::SpawnFailed(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(spawn.exit_code(), 1);
+        assert!(std::error::Error::source(&spawn).is_some());
+
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        let error = Error::spawning("terminal", OsStr::new("foot"), missing, Error::SpawnFailed);
+        assert_eq!(error.to_string(), "terminal 'foot' not found in PATH.");
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let error = Error::spawning("terminal", OsStr::new("foot"), denied, Error::SpawnFailed);
+        assert!(matches!(error, Error::SpawnFailed(_)));
+        let too_long = io::Error::from_raw_os_error(libc::E2BIG);
+        let error = Error::spawning(
+            "application",
+            OsStr::new("app"),
+            too_long,
+            Error::SpawnFailed,
+        );
+        assert!(matches!(error, Error::ArgumentsTooLong { program } if program == "app"));
+    }
+}
@@ -0,0 +1,1186 @@
+//! Expanding Exec keys and launching the selected item.
+
+use std::borrow::Cow;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::entry::{DesktopEntry, EntryKind};
+use crate::error::Error;
+use crate::format::Format;
+use crate::history::{self, History};
+use crate::menu::{
+    find_by_format, find_by_keyword, find_by_prefix, find_item, find_item_ignoring_case,
+    find_with_arguments, MenuItem,
+};
+use crate::{dbus, startup, terminal, Cli, Scope};
+
+/// Command prefix running a command in a transient systemd scope of the user's service manager.
+const SYSTEMD_SCOPE: [&str; 4] = ["systemd-run", "--user", "--scope", "--"];
+
+/// The longest single argument Linux accepts.
+const MAX_ARG_STRLEN: usize = 32 * 4096;
+
+/// Launches the item matching the selection.
+pub(crate) fn launch(cli: &Cli, items: &[MenuItem], selection: &str) -> Result<(), Error> {
+    if selection.is_empty() {
+        return Err(Error::Cancelled);
+    }
+    let selected_item = find_item(items, selection)
+        .or_else(|| {
+            cli.ignore_case
+                .then(|| find_item_ignoring_case(items, selection))
+                .flatten()
+        })
+        .or_else(|| {
+            find_by_format(
+                items,
+                selection,
+                &cli.with_command_basename(Format::from(cli.match_type()?)),
+            )
+        })
+        .or_else(|| find_by_keyword(items, selection))
+        .or_else(|| {
+            cli.match_prefix
+                .then(|| find_by_prefix(items, selection))
+                .flatten()
+        });
+    let mut extra_args = Vec::new();
+    let selected_item = selected_item.or_else(|| {
+        let (item, rest) = cli
+            .allow_append
+            .then(|| find_with_arguments(items, selection))
+            .flatten()?;
+        extra_args = shlex::split(rest)?;
+        Some(item)
+    });
+    let Some(selected_item) = selected_item else {
+        if !cli.exec_unmatched {
+            return Err(Error::NoMatch(selection.to_owned()));
+        }
+        let in_terminal = cli
+            .terminal_prefix
+            .as_deref()
+            .and_then(|prefix| selection.strip_prefix(prefix));
+        let split = shlex::split(in_terminal.unwrap_or(selection)).unwrap_or_default();
+        let Some((program, args)) = split.split_first() else {
+            return Err(Error::InvalidCommand);
+        };
+        if in_terminal.is_some() {
+            return run_in_terminal(cli, &split);
+        }
+        let mut command = Command::new(program);
+        command.args(args);
+        if cli.is_dry_run() {
+            print_dry_run(cli, [&command]);
+            return Ok(());
+        }
+        let output = command.output().map_err(|e| {
+            Error::spawning("command", command.get_program(), e, Error::SpawnFailed)
+        })?;
+        println!("{}", String::from_utf8_lossy(&output.stdout));
+        eprintln!(
+            "Command exited with status {}",
+            output.status.code().unwrap_or(-1)
+        );
+        // Any failure of the command exits with 1, its own status could be mistaken for 2 or 3.
+        return match output.status.success() {
+            true => Ok(()),
+            false => Err(Error::Exited(1)),
+        };
+    };
+    launch_item(cli, selected_item, &extra_args)
+}
+
+/// Launches an unmatched command in the terminal, detached like an entry rather than waited for.
+fn run_in_terminal(cli: &Cli, argv: &[String]) -> Result<(), Error> {
+    let mut command = terminal_command(cli, argv)?;
+    if cli.is_dry_run() {
+        print_dry_run(cli, [&command]);
+        return Ok(());
+    }
+    if !cli.no_detach {
+        detach(&mut command);
+    }
+    log_output(cli, &mut command, &argv[0]);
+    let mut child = command
+        .spawn()
+        .map_err(|e| Error::spawning("terminal", command.get_program(), e, Error::SpawnFailed))?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Builds the command running `argv` in --terminal or the detected terminal.
+fn terminal_command(cli: &Cli, argv: &[String]) -> Result<Command, Error> {
+    let terminal = terminal(cli).ok_or(Error::NoTerminal)?;
+    let wrapped = wrap_in_terminal(&terminal, argv)
+        .filter(|w| !w.is_empty())
+        .ok_or(Error::InvalidTerminal)?;
+    let mut command = Command::new(&wrapped[0]);
+    command.args(&wrapped[1..]);
+    Ok(command)
+}
+
+/// Launches an entry or one of its actions, with `extra_args` after the arguments of its Exec key.
+pub(crate) fn launch_item(
+    cli: &Cli,
+    selected_item: &MenuItem,
+    extra_args: &[String],
+) -> Result<(), Error> {
+    let selected_entry = selected_item.entry;
+    // DBus activation has no way to pass the extra arguments.
+    if selected_entry.dbus_activatable
+        && !cli.no_dbus
+        && !cli.is_dry_run()
+        && extra_args.is_empty()
+        && dbus::activate(selected_entry, selected_item.action, &cli.args)
+    {
+        record_launch(selected_entry);
+        return Ok(());
+    }
+    if let EntryKind::Link(url) = &selected_entry.kind {
+        let mut command = url_command(cli, url)?;
+        if cli.is_dry_run() {
+            print_dry_run(cli, [&command]);
+            return Ok(());
+        }
+        if !cli.no_detach {
+            detach(&mut command);
+        }
+        log_output(cli, &mut command, &selected_entry.filename);
+        let mut child = command.spawn().map_err(|e| {
+            Error::spawning("URL opener", command.get_program(), e, Error::SpawnFailed)
+        })?;
+        thread::spawn(move || child.wait());
+        record_launch(selected_entry);
+        return Ok(());
+    }
+
+    let terminal = terminal_for(cli, selected_entry);
+    let scope = cli.scope.as_ref().filter(|scope| match scope {
+        Scope::Systemd => {
+            let found = find_in_path(SYSTEMD_SCOPE[0]).is_some();
+            if !found && cli.verbose {
+                eprintln!("systemd-run was not found, launching directly");
+            }
+            found
+        }
+    });
+
+    if cli.verbose {
+        let (unknown, trailing) = malformed_field_codes(selected_item.exec());
+        for code in unknown {
+            eprintln!(
+                "Removing the unknown field code %{} from the Exec key",
+                code
+            );
+        }
+        if trailing {
+            eprintln!("Removing the trailing % from the Exec key");
+        }
+    }
+    let shell = cli.shell_wrap.then(login_shell);
+    let commands = item_commands(
+        selected_item,
+        &cli.args,
+        extra_args,
+        terminal.as_deref(),
+        scope,
+        shell.as_deref(),
+    )?;
+    if cli.is_dry_run() {
+        print_dry_run(cli, commands.iter().map(|(_, command)| command));
+        return Ok(());
+    }
+
+    let mut launched = false;
+    let mut errors = Vec::new();
+    for (sequence, (program, mut command)) in commands.into_iter().enumerate() {
+        // Never pass on the startup ID this process was launched with.
+        command
+            .env_remove("DESKTOP_STARTUP_ID")
+            .env_remove("XDG_ACTIVATION_TOKEN");
+        if selected_entry.startup_notify && !cli.no_startup_notify {
+            let id = startup::id(&program, sequence);
+            command
+                .env("DESKTOP_STARTUP_ID", &id)
+                .env("XDG_ACTIVATION_TOKEN", &id);
+        }
+        if let Some(wm_class) = &selected_entry.startup_wm_class {
+            command.env(startup::WM_CLASS_VAR, wm_class);
+        }
+
+        if !cli.no_detach {
+            detach(&mut command);
+        }
+        log_output(cli, &mut command, &selected_entry.filename);
+        match command.spawn() {
+            Ok(mut child) => {
+                // Reap the child once it exits, which matters to a long running --daemon.
+                thread::spawn(move || child.wait());
+                launched = true;
+            }
+            Err(e) => {
+                let role = if shell.is_some() {
+                    "shell"
+                } else if terminal.is_some() {
+                    "terminal"
+                } else {
+                    "application"
+                };
+                errors.push(Error::spawning(
+                    role,
+                    command.get_program(),
+                    e,
+                    Error::SpawnFailed,
+                ));
+            }
+        }
+    }
+    if !launched && !errors.is_empty() {
+        let first = errors.remove(0);
+        errors.iter().for_each(Error::report);
+        return Err(first);
+    }
+    // Some of the files given to an entry launched once per file were still opened.
+    for e in errors {
+        eprintln!("Could not open every file: {}", e);
+    }
+
+    record_launch(selected_entry);
+    Ok(())
+}
+
+/// Returns the terminal an entry is launched in, if any: --terminal or a detected terminal for
+/// entries with Terminal=true, and --terminal for every entry with --terminal-always.
+fn terminal_for(cli: &Cli, entry: &DesktopEntry) -> Option<String> {
+    if !entry.terminal && !cli.terminal_always {
+        return None;
+    }
+    terminal(cli)
+}
+
+/// Returns the template of --terminal, or of the first installed terminal unless
+/// --no-auto-terminal is given.
+fn terminal(cli: &Cli) -> Option<String> {
+    match &cli.terminal {
+        Some(terminal) => Some(terminal::with_exec_arg(terminal, &cli.terminal_exec_arg)),
+        None if !cli.no_auto_terminal => terminal::detect(&cli.terminal_order),
+        None => None,
+    }
+}
+
+/// Builds the commands launching an item with the given files or URLs, each with the name of the
+/// program it runs, which is once per file when the Exec key takes a single one. The extra
+/// arguments follow the expanded Exec key of every command.
+fn item_commands(
+    item: &MenuItem,
+    args: &[String],
+    extra_args: &[String],
+    terminal: Option<&str>,
+    scope: Option<&Scope>,
+    shell: Option<&str>,
+) -> Result<Vec<(String, Command)>, Error> {
+    let entry = item.entry;
+    let mut commands = Vec::new();
+    let budget = match shell {
+        // The command line is a single argument of the shell, which Linux limits on its own.
+        Some(_) => argument_budget().min(MAX_ARG_STRLEN / 2),
+        None => argument_budget(),
+    };
+    for command_string in expand_invocations(item.exec(), entry, args, budget) {
+        let Some(exec_split) = parse_exec(&command_string) else {
+            return Err(Error::InvalidExec);
+        };
+        let (variables, mut exec_split) = split_assignments(exec_split);
+        exec_split.extend_from_slice(extra_args);
+        if let Some(terminal) = terminal {
+            let Some(wrapped) = wrap_in_terminal(terminal, &exec_split) else {
+                return Err(Error::InvalidTerminal);
+            };
+            exec_split = wrapped;
+        }
+        if exec_split.is_empty() {
+            return Err(Error::EmptyExec);
+        }
+        let program = exec_split[0].clone();
+        if let Some(Scope::Systemd) = scope {
+            exec_split.splice(0..0, SYSTEMD_SCOPE.map(String::from));
+        }
+        if let Some(shell) = shell {
+            exec_split = wrap_in_shell(shell, &exec_split);
+        }
+        let mut command = entry_command(entry, &exec_split);
+        // Variables set in the Exec key take precedence over the entry's environment keys.
+        command
+            .envs(entry.environment.iter().cloned())
+            .envs(variables);
+        commands.push((program, command));
+    }
+    Ok(commands)
+}
+
+/// Returns $SHELL, or /bin/sh when it is not set.
+fn login_shell() -> String {
+    env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "/bin/sh".to_owned())
+}
+
+/// Runs the arguments through a login shell, which replaces itself with the command once its
+/// profile is read. The working directory is kept, since the shell is started in it.
+fn wrap_in_shell(shell: &str, argv: &[String]) -> Vec<String> {
+    let words: Vec<Cow<str>> = argv.iter().map(|arg| quote(arg)).collect();
+    vec![
+        shell.to_owned(),
+        "-lc".to_owned(),
+        format!("exec {}", words.join(" ")),
+    ]
+}
+
+/// Builds the --url-opener command opening a URL.
+fn url_command(cli: &Cli, url: &str) -> Result<Command, Error> {
+    let split = shlex::split(&cli.url_opener).unwrap_or_default();
+    let Some((program, args)) = split.split_first() else {
+        return Err(Error::InvalidUrlOpener);
+    };
+    let mut command = Command::new(program);
+    command.args(args).arg(url);
+    Ok(command)
+}
+
+fn record_launch(entry: &DesktopEntry) {
+    let mut history = History::load();
+    history.record(&entry.filename, history::now());
+    if let Err(e) = history.save() {
+        eprintln!("Could not save launch history: {}", e);
+    }
+}
+
+/// Expands the field codes of an entry's or action's Exec key as described by the desktop entry
+/// specification, substituting `args` for the file and URL codes.
+fn expand_exec(exec: &str, entry: &DesktopEntry, args: &[String]) -> String {
+    let mut expanded = String::with_capacity(exec.len());
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('f' | 'u') => {
+                if let Some(arg) = args.first() {
+                    expanded.push_str(&quote_exec(arg));
+                }
+            }
+            Some('F' | 'U') => {
+                let quoted: Vec<_> = args.iter().map(|a| quote_exec(a)).collect();
+                expanded.push_str(&quoted.join(" "));
+            }
+            Some('c') => expanded.push_str(&quote_exec(&entry.name)),
+            Some('k') => expanded.push_str(&quote_exec(&entry.location.to_string_lossy())),
+            Some('i') => {
+                if let Some(icon) = &entry.icon {
+                    expanded.push_str("--icon ");
+                    expanded.push_str(&quote_exec(icon));
+                }
+            }
+            // Deprecated and unknown field codes, and a trailing %, are removed.
+            Some(_) | None => {}
+        }
+    }
+    expanded
+}
+
+/// Returns the field codes of an Exec key that are neither defined nor deprecated, and whether it
+/// ends with a lone `%`, which expand_exec both drops.
+pub(crate) fn malformed_field_codes(exec: &str) -> (Vec<char>, bool) {
+    let mut unknown = Vec::new();
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        match chars.next() {
+            Some('%' | 'f' | 'F' | 'u' | 'U' | 'c' | 'k' | 'i') => {}
+            Some('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+            Some(other) => unknown.push(other),
+            None => return (unknown, true),
+        }
+    }
+    (unknown, false)
+}
+
+/// Substitutes a command into a terminal template. A `{}` argument is replaced by the command's
+/// arguments, and a `{}` within an argument, as in `sh -c 'term -e {}'`, by the quoted command.
+fn wrap_in_terminal(template: &str, argv: &[String]) -> Option<Vec<String>> {
+    let mut wrapped = Vec::new();
+    for arg in shlex::split(template)? {
+        if arg == "{}" {
+            wrapped.extend_from_slice(argv);
+        } else if arg.contains("{}") {
+            let joined = shlex::try_join(argv.iter().map(String::as_str)).ok()?;
+            wrapped.push(arg.replace("{}", &joined));
+        } else {
+            wrapped.push(arg);
+        }
+    }
+    Some(wrapped)
+}
+
+/// Splits the leading `KEY=VALUE` arguments off a command, which a shell would run with those
+/// environment variables set.
+pub(crate) fn split_assignments(mut argv: Vec<String>) -> (Vec<(String, String)>, Vec<String>) {
+    let is_name = |name: &str| {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let count = argv
+        .iter()
+        .take_while(|arg| arg.split_once('=').is_some_and(|(name, _)| is_name(name)))
+        .count();
+    let variables = argv
+        .drain(..count)
+        .map(|arg| {
+            let (name, value) = arg.split_once('=').unwrap_or_default();
+            (name.to_owned(), value.to_owned())
+        })
+        .collect();
+    (variables, argv)
+}
+
+/// Describes a command as a shell command line, including its working directory and the
+/// environment variables it sets.
+fn describe(command: &Command) -> String {
+    let mut words = Vec::new();
+    if let Some(dir) = command.get_current_dir() {
+        words.push("cd".to_owned());
+        words.push(quote(&dir.to_string_lossy()).into_owned());
+        words.push("&&".to_owned());
+    }
+    for (name, value) in command.get_envs() {
+        if let Some(value) = value {
+            let assignment = format!("{}={}", name.to_string_lossy(), value.to_string_lossy());
+            words.push(quote(&assignment).into_owned());
+        }
+    }
+    let program = command.get_program().to_string_lossy();
+    words.push(quote(&program).into_owned());
+    for arg in command.get_args() {
+        words.push(quote(&arg.to_string_lossy()).into_owned());
+    }
+    words.join(" ")
+}
+
+/// Prints the commands instead of running them, one per line for --dry-run or as a single shell
+/// command line starting them all for --output-selected.
+fn print_dry_run<'a>(cli: &Cli, commands: impl IntoIterator<Item = &'a Command>) {
+    println!("{}", dry_run_output(cli, commands));
+}
+
+fn dry_run_output<'a>(cli: &Cli, commands: impl IntoIterator<Item = &'a Command>) -> String {
+    let lines: Vec<String> = commands.into_iter().map(describe).collect();
+    match cli.output_selected {
+        true => lines.join(" & "),
+        false => lines.join("\n"),
+    }
+}
+
+/// Runs a command in a new session with its standard streams on /dev/null, so that it outlives this
+/// process and its terminal.
+fn detach(command: &mut Command) {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe and the closure touches no other state.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Sends the output of a command to the end of the --log-file after a line naming the entry and the
+/// command, leaving the output where it was when the file can't be opened.
+fn log_output(cli: &Cli, command: &mut Command, name: &str) {
+    let Some(path) = &cli.log_file else {
+        return;
+    };
+    let open = || -> io::Result<File> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(
+            file,
+            "--- {} {}: {}",
+            history::format_time(history::now()),
+            name,
+            describe(command)
+        )?;
+        Ok(file)
+    };
+    match open().and_then(|file| Ok((file.try_clone()?, file))) {
+        Ok((stdout, stderr)) => {
+            command.stdout(stdout).stderr(stderr);
+        }
+        Err(e) => eprintln!("Could not write to {}: {}", path.display(), e),
+    }
+}
+
+/// Builds the command running `argv`, which must not be empty, for an entry in the entry's working
+/// directory.
+fn entry_command(entry: &DesktopEntry, argv: &[String]) -> Command {
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    if let Some(path) = &entry.path {
+        command.current_dir(path);
+    }
+    command
+}
+
+/// Expands an Exec key once per launch, which is once per file when only the single file or URL
+/// codes are used and several files are given, and once with every file otherwise. Like xargs, the
+/// files of the multiple file or URL codes are split across launches taking at most `budget` bytes
+/// of arguments each.
+fn expand_invocations(
+    exec: &str,
+    entry: &DesktopEntry,
+    args: &[String],
+    budget: usize,
+) -> Vec<String> {
+    let mut single = false;
+    let mut multiple = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            match chars.next() {
+                Some('f' | 'u') => single = true,
+                Some('F' | 'U') => multiple = true,
+                _ => {}
+            }
+        }
+    }
+
+    match single && !multiple && args.len() > 1 {
+        true => args
+            .iter()
+            .map(|arg| expand_exec(exec, entry, std::slice::from_ref(arg)))
+            .collect(),
+        false if multiple => chunk_args(args, budget)
+            .into_iter()
+            .map(|chunk| expand_exec(exec, entry, chunk))
+            .collect(),
+        false => vec![expand_exec(exec, entry, args)],
+    }
+}
+
+/// Returns the bytes the arguments of a launch may take, half of ARG_MAX less the environment, which
+/// leaves room for the rest of the Exec key and a terminal or systemd-run prefix.
+fn argument_budget() -> usize {
+    // SAFETY: sysconf has no preconditions.
+    let arg_max = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+    let arg_max = usize::try_from(arg_max).unwrap_or(128 * 1024);
+    let environment: usize = env::vars_os()
+        .map(|(name, value)| argument_size(name.len() + 1 + value.len()))
+        .sum();
+    arg_max.saturating_sub(environment) / 2
+}
+
+/// Returns the bytes an argument takes in the argument list, its terminating NUL and its pointer.
+fn argument_size(len: usize) -> usize {
+    len + 1 + std::mem::size_of::<usize>()
+}
+
+/// Splits the files into consecutive runs each taking at most `budget` bytes, except for a single
+/// file taking more on its own.
+fn chunk_args(args: &[String], budget: usize) -> Vec<&[String]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    for (i, arg) in args.iter().enumerate() {
+        let arg_size = argument_size(arg.len());
+        if i > start && size + arg_size > budget {
+            chunks.push(&args[start..i]);
+            start = i;
+            size = 0;
+        }
+        size += arg_size;
+    }
+    chunks.push(&args[start..]);
+    chunks
+}
+
+/// Splits an Exec key into arguments following the quoting rules of the desktop entry
+/// specification, returning `None` for an unterminated quote.
+///
+/// Arguments are separated by spaces and may be quoted in double quotes, within which `"`, `` ` ``,
+/// `$` and `\` are escaped with a backslash. Unlike in a shell, single quotes and everything outside
+/// of double quotes are taken literally.
+pub(crate) fn parse_exec(exec: &str) -> Option<Vec<String>> {
+    let mut argv = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {
+                if in_arg {
+                    argv.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '`' | '$' | '\\') => arg.push(c),
+                            c => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                        },
+                        c => arg.push(c),
+                    }
+                }
+            }
+            c => {
+                in_arg = true;
+                arg.push(c);
+            }
+        }
+    }
+    if in_arg {
+        argv.push(arg);
+    }
+    Some(argv)
+}
+
+/// Quotes a string so that parse_exec returns it as a single argument.
+fn quote_exec(s: &str) -> Cow<'_, str> {
+    const RESERVED: &str = " \t\n\"'\\><~|&;$*?#()`";
+    if !s.is_empty() && !s.contains(|c| RESERVED.contains(c)) {
+        return Cow::Borrowed(s);
+    }
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Quotes a string so that it survives being split by shlex.
+fn quote(s: &str) -> Cow<'_, str> {
+    shlex::try_quote(s).unwrap_or_default()
+}
+
+/// Finds a program in `$PATH`, or checks it directly if it contains a slash.
+pub(crate) fn find_in_path(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(|p| p.exists());
+    }
+    let path_var = env::var_os("PATH").unwrap_or_default();
+    env::split_paths(&path_var)
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.join(program))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::entry::{unescape, Environment};
+    use crate::menu::menu_items;
+    use crate::test_support::{parse, temp_dir};
+    use crate::EntryType;
+    use clap::Parser;
+
+    fn entry(exec: &str) -> DesktopEntry {
+        parse(
+            &format!("[Desktop Entry]\nType=Application\nName=My App\nIcon=my-icon\nExec={exec}\n"),
+            &Environment::default(),
+        )
+        .unwrap()
+    }
+
+    fn expand(exec: &str, args: &[String]) -> String {
+        let entry = entry(exec);
+        expand_exec(&entry.exec, &entry, args)
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn terminal_arguments_stay_separate() {
+        let argv = args(&["app", "a b", "it's"]);
+        assert_eq!(
+            wrap_in_terminal("foot --title 'My Term' {}", &argv).unwrap(),
+            ["foot", "--title", "My Term", "app", "a b", "it's"]
+        );
+
+        let wrapped = wrap_in_terminal("sh -c 'term -e {}'", &argv).unwrap();
+        assert_eq!(wrapped[..2], ["sh", "-c"]);
+        let script = wrapped[2].strip_prefix("term -e ").unwrap();
+        assert_eq!(shlex::split(script).unwrap(), argv);
+
+        let entry = entry("printf \"%%s|\" \"a b\" \"it's\"");
+        let argv = parse_exec(&expand_exec(&entry.exec, &entry, &[])).unwrap();
+        let wrapped = wrap_in_terminal("sh -c 'exec {}'", &argv).unwrap();
+        let output = entry_command(&entry, &wrapped).output().unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "a b|it's|");
+    }
+
+    #[test]
+    fn leading_assignments_become_variables() {
+        let (variables, argv) = split_assignments(args(&["FOO=bar", "mycmd", "--flag", "A=b"]));
+        assert_eq!(variables, [("FOO".to_owned(), "bar".to_owned())]);
+        assert_eq!(argv, ["mycmd", "--flag", "A=b"]);
+
+        let (variables, argv) = split_assignments(args(&["_A1=", "B=c=d", "=x", "cmd"]));
+        assert_eq!(
+            variables,
+            [
+                ("_A1".to_owned(), String::new()),
+                ("B".to_owned(), "c=d".to_owned())
+            ]
+        );
+        assert_eq!(argv, ["=x", "cmd"]);
+        assert_eq!(split_assignments(args(&["1A=b", "cmd"])).1, ["1A=b", "cmd"]);
+
+        let entry = entry("FOO=bar sh -c \"printf %s \\\"\\$FOO\\\"\"");
+        let (variables, argv) = split_assignments(parse_exec(&entry.exec).unwrap());
+        let output = entry_command(&entry, &argv)
+            .envs(variables)
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "bar");
+    }
+
+    #[test]
+    fn dry_run_description() {
+        let entry = entry("\"FOO=a b\" app --title \"My App\" %f");
+        let (variables, argv) = split_assignments(
+            parse_exec(&expand_exec(&entry.exec, &entry, &args(&["it's"]))).unwrap(),
+        );
+        let mut command = entry_command(&entry, &argv);
+        command.envs(variables).current_dir("/tmp/my dir");
+        let description = describe(&command);
+        assert_eq!(
+            description,
+            "cd '/tmp/my dir' && 'FOO=a b' app --title 'My App' \"it's\""
+        );
+        assert_eq!(
+            shlex::split(&description).unwrap()[4..],
+            ["app", "--title", "My App", "it's"]
+        );
+    }
+
+    #[test]
+    fn detached_commands_run_in_a_new_session() {
+        let entry = entry("sh -c \"cut -d ' ' -f 6 /proc/\\$\\$/stat > \\\"\\$0\\\"\"");
+        let dir = temp_dir("detach");
+        let out = dir.join("sid");
+        let mut argv = parse_exec(&entry.exec).unwrap();
+        argv.push(out.to_string_lossy().into_owned());
+        let mut command = entry_command(&entry, &argv);
+        detach(&mut command);
+        let mut child = command.spawn().unwrap();
+        child.wait().unwrap();
+        let sid: u32 = fs::read_to_string(&out).unwrap().trim().parse().unwrap();
+        // The child leads the session it created.
+        assert_eq!(sid, child.id());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn output_is_appended_to_the_log_file() {
+        let dir = temp_dir("log");
+        let log = dir.join("launch.log");
+        fs::write(&log, "earlier\n").unwrap();
+        let cli = Cli::parse_from(["dmenu-desktop", "--log-file", log.to_str().unwrap()]);
+        let entry = entry("sh -c \"echo out; echo err >&2\"");
+        let mut command = entry_command(&entry, &parse_exec(&entry.exec).unwrap());
+        detach(&mut command);
+        log_output(&cli, &mut command, &entry.filename);
+        command.status().unwrap();
+        let contents = fs::read_to_string(&log).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "earlier");
+        assert!(
+            lines[1].starts_with("--- ")
+                && lines[1].ends_with(" app: sh -c 'echo out; echo err >&2'")
+        );
+        assert_eq!(lines[2..], ["out", "err"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn terminal_always() {
+        let graphical = entry("app");
+        let cli = Cli::parse_from(["dmenu-desktop", "--terminal", "foot"]);
+        assert_eq!(terminal_for(&cli, &graphical), None);
+        let cli = Cli::parse_from(["dmenu-desktop", "--terminal", "foot", "--terminal-always"]);
+        assert_eq!(
+            terminal_for(&cli, &graphical).as_deref(),
+            Some("foot -e {}")
+        );
+        assert!(Cli::try_parse_from(["dmenu-desktop", "--terminal-always"]).is_err());
+    }
+
+    #[test]
+    fn unmatched_commands_in_the_terminal() {
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--exec-unmatched",
+            "--terminal-prefix",
+            "!",
+            "--terminal",
+            "foot",
+            "--dry-run",
+        ]);
+        let command = terminal_command(&cli, &args(&["htop", "-d", "5"])).unwrap();
+        assert_eq!(describe(&command), "foot -e htop -d 5");
+        let entries = [entry("app")];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        assert!(launch(&cli, &items, "!htop").is_ok());
+        assert!(matches!(
+            launch(&cli, &items, "! "),
+            Err(Error::InvalidCommand)
+        ));
+
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--exec-unmatched",
+            "--terminal-prefix",
+            "!",
+            "--no-auto-terminal",
+        ]);
+        assert!(matches!(
+            terminal_command(&cli, &args(&["htop"])),
+            Err(Error::NoTerminal)
+        ));
+        assert!(Cli::try_parse_from(["dmenu-desktop", "--terminal-prefix", "!"]).is_err());
+    }
+
+    #[test]
+    fn missing_programs_are_not_found() {
+        let missing = "dmenu-desktop-missing-program";
+        let cli = Cli::parse_from(["dmenu-desktop", "--no-detach"]);
+        let entries = [entry(missing)];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        assert_eq!(
+            launch(&cli, &items, "My App").unwrap_err().to_string(),
+            format!("application '{missing}' not found in PATH.")
+        );
+
+        let mut terminal_entry = entry("htop");
+        terminal_entry.terminal = true;
+        let cli = Cli::parse_from(["dmenu-desktop", "--no-detach", "--terminal", missing]);
+        let entries = [terminal_entry];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        assert_eq!(
+            launch(&cli, &items, "My App").unwrap_err().to_string(),
+            format!("terminal '{missing}' not found in PATH.")
+        );
+
+        let cli = Cli::parse_from(["dmenu-desktop", "--exec-unmatched"]);
+        assert_eq!(
+            launch(&cli, &[], &format!("{missing} --flag"))
+                .unwrap_err()
+                .to_string(),
+            format!("command '{missing}' not found in PATH.")
+        );
+    }
+
+    #[test]
+    fn failed_commands_exit_with_1() {
+        let cli = Cli::parse_from(["dmenu-desktop", "--exec-unmatched"]);
+        for command in ["sh -c 'exit 2'", "sh -c 'exit 3'", "sh -c 'kill $$'"] {
+            assert_eq!(launch(&cli, &[], command).unwrap_err().exit_code(), 1);
+        }
+        assert!(launch(&cli, &[], "true").is_ok());
+    }
+
+    #[test]
+    fn links_open_with_the_url_opener() {
+        let cli = Cli::parse_from(["dmenu-desktop"]);
+        let command = url_command(&cli, "https://example.com/a b").unwrap();
+        assert_eq!(describe(&command), "xdg-open 'https://example.com/a b'");
+        let cli = Cli::parse_from(["dmenu-desktop", "--url-opener", "firefox --new-tab"]);
+        let command = url_command(&cli, "https://example.com").unwrap();
+        assert_eq!(describe(&command), "firefox --new-tab https://example.com");
+        let cli = Cli::parse_from(["dmenu-desktop", "--url-opener", ""]);
+        assert!(matches!(
+            url_command(&cli, "https://example.com"),
+            Err(Error::InvalidUrlOpener)
+        ));
+    }
+
+    #[test]
+    fn launches_run_in_entry_path() {
+        let dir = temp_dir("entry-path").canonicalize().unwrap();
+        let contents = format!(
+            "[Desktop Entry]\nType=Application\nName=App\nExec=pwd\nPath={}\n\
+            Actions=pwd;\n[Desktop Action pwd]\nName=Pwd\nExec=sh -c pwd\n",
+            dir.display()
+        );
+        let entries = [parse(&contents, &Environment::default()).unwrap()];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        assert_eq!(items.len(), 2);
+        for item in &items {
+            let command_string = &expand_invocations(item.exec(), item.entry, &[], usize::MAX)[0];
+            let argv = parse_exec(command_string).unwrap();
+            let output = entry_command(item.entry, &argv).output().unwrap();
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap().trim(),
+                dir.to_str().unwrap()
+            );
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn exec_quoting() {
+        let parse = |exec| parse_exec(&unescape(exec));
+        assert_eq!(
+            parse("\"/opt/my app/bin\" --flag \"a b\"").unwrap(),
+            ["/opt/my app/bin", "--flag", "a b"]
+        );
+        // Backslashes are escaped once more by the string escapes of the file.
+        assert_eq!(
+            parse("sh -c \"echo \\\\\"\\\\$HOME\\\\\" \\\\`date\\\\`\"").unwrap(),
+            ["sh", "-c", "echo \"$HOME\" `date`"]
+        );
+        assert_eq!(parse("app \"\\\\\\\\\"").unwrap(), ["app", "\\"]);
+        assert_eq!(parse("app \"\" a\"b c\"d").unwrap(), ["app", "", "ab cd"]);
+        assert_eq!(
+            parse("app  'a b' $HOME").unwrap(),
+            ["app", "'a", "b'", "$HOME"]
+        );
+        assert_eq!(parse("app \"a"), None);
+        assert_eq!(parse("app \"a\\\\"), None);
+
+        for arg in ["plain", "", "my file", "\"$HOME\" `x` \\", "it's"] {
+            let exec = format!("app {}", quote_exec(arg));
+            assert_eq!(parse_exec(&exec).unwrap(), ["app", arg]);
+        }
+    }
+
+    #[test]
+    fn expand_single_file_and_url() {
+        let files = args(&["a.txt", "b.txt"]);
+        assert_eq!(expand("app %f", &files), "app a.txt");
+        assert_eq!(expand("app %u", &files), "app a.txt");
+    }
+
+    #[test]
+    fn expand_file_and_url_lists() {
+        let files = args(&["a.txt", "my file.txt"]);
+        assert_eq!(expand("app %F", &files), "app a.txt \"my file.txt\"");
+        assert_eq!(expand("app %U", &files), "app a.txt \"my file.txt\"");
+    }
+
+    #[test]
+    fn expand_without_args() {
+        for code in ["%f", "%F", "%u", "%U"] {
+            let expanded = expand(&format!("app {code}"), &[]);
+            assert_eq!(parse_exec(&expanded).unwrap(), ["app"]);
+        }
+    }
+
+    #[test]
+    fn expand_name_location_and_icon() {
+        assert_eq!(expand("app %c", &[]), "app \"My App\"");
+        assert_eq!(expand("app %k", &[]), "app /apps/app.desktop");
+        assert_eq!(expand("app %i", &[]), "app --icon my-icon");
+    }
+
+    #[test]
+    fn commands_per_file() {
+        let files = args(&["a.txt", "my file.txt"]);
+        let described = |exec: &str| {
+            let entries = [entry(exec)];
+            let items = menu_items(&entries, &Format::from(&EntryType::Name));
+            let commands = item_commands(&items[0], &files, &[], None, None, None).unwrap();
+            commands
+                .iter()
+                .map(|(_, c)| describe(c))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(described("app %f"), ["app a.txt", "app 'my file.txt'"]);
+        assert_eq!(described("app %U"), ["app a.txt 'my file.txt'"]);
+        assert_eq!(described("app"), ["app"]);
+        let entries = [entry("app %f")];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let extra_args = args(&["--new-tab"]);
+        let commands = item_commands(&items[0], &files, &extra_args, None, None, None).unwrap();
+        assert_eq!(describe(&commands[1].1), "app 'my file.txt' --new-tab");
+        assert_eq!(
+            described("FOO=bar app %u"),
+            ["'FOO=bar' app a.txt", "'FOO=bar' app 'my file.txt'"]
+        );
+    }
+
+    #[test]
+    fn shell_wrapped_commands() {
+        let wrapped = |exec: &str, files: &[String]| {
+            let mut entry = entry(exec);
+            entry.path = Some(PathBuf::from("/tmp"));
+            let entries = [entry];
+            let items = menu_items(&entries, &Format::from(&EntryType::Name));
+            let mut commands =
+                item_commands(&items[0], files, &[], None, None, Some("sh")).unwrap();
+            commands.remove(0)
+        };
+        let (program, command) = wrapped("app --title \"My App\" %f", &args(&["it's"]));
+        assert_eq!(program, "app");
+        assert_eq!(command.get_program(), "sh");
+        let argv: Vec<_> = command.get_args().collect();
+        assert_eq!(argv[0], "-lc");
+        assert_eq!(
+            shlex::split(argv[1].to_str().unwrap()).unwrap(),
+            ["exec", "app", "--title", "My App", "it's"]
+        );
+
+        let (_, mut command) = wrapped("sh -c \"printenv FOO; pwd\"", &[]);
+        let output = command.env("FOO", "bar").output().unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "bar\n/tmp\n");
+    }
+
+    #[test]
+    fn output_selected_is_one_line() {
+        let entries = [entry("app --title \"My App\" %f")];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let commands =
+            item_commands(&items[0], &args(&["a.txt", "b.txt"]), &[], None, None, None).unwrap();
+        let output = |flag: &str| {
+            let cli = Cli::parse_from(["dmenu-desktop", flag]);
+            dry_run_output(&cli, commands.iter().map(|(_, c)| c))
+        };
+        assert_eq!(
+            output("--dry-run"),
+            "app --title 'My App' a.txt\napp --title 'My App' b.txt"
+        );
+        assert_eq!(
+            output("--output-selected"),
+            "app --title 'My App' a.txt & app --title 'My App' b.txt"
+        );
+        assert!(Cli::try_parse_from(["dmenu-desktop", "--output-selected", "--verbose"]).is_err());
+    }
+
+    #[test]
+    fn malformed_field_codes_are_removed() {
+        assert_eq!(expand("app --width=50%", &[]), "app --width=50");
+        assert_eq!(expand("app %z --flag", &[]), "app  --flag");
+        assert_eq!(expand("app 100%% %d%D", &[]), "app 100% ");
+        assert_eq!(expand("app %", &args(&["a.txt"])), "app ");
+        assert_eq!(parse_exec(&expand("app %z %", &[])).unwrap(), ["app"]);
+        assert_eq!(
+            malformed_field_codes("app %z %% %f %y %"),
+            (vec!['z', 'y'], true)
+        );
+        assert_eq!(malformed_field_codes("app %%"), (vec![], false));
+    }
+
+    #[test]
+    fn invocations_per_file() {
+        let files = args(&["a.txt", "b.txt"]);
+        let single = entry("app %f");
+        let invocations = expand_invocations(&single.exec, &single, &files, usize::MAX);
+        assert_eq!(invocations, ["app a.txt", "app b.txt"]);
+        let multiple = entry("app %F");
+        let invocations = expand_invocations(&multiple.exec, &multiple, &files, usize::MAX);
+        assert_eq!(invocations, ["app a.txt b.txt"]);
+        let invocations = expand_invocations(&single.exec, &single, &[], usize::MAX);
+        assert_eq!(invocations, ["app "]);
+        let invocations = expand_invocations(&multiple.exec, &multiple, &[], 0);
+        assert_eq!(invocations, ["app "]);
+    }
+
+    #[test]
+    fn long_file_lists_are_split() {
+        let files = args(&["a.txt", "b.txt", "c.txt"]);
+        let multiple = entry("app %F");
+        let budget = 2 * argument_size("a.txt".len());
+        let invocations = expand_invocations(&multiple.exec, &multiple, &files, budget);
+        assert_eq!(invocations, ["app a.txt b.txt", "app c.txt"]);
+        let invocations = expand_invocations(&multiple.exec, &multiple, &files, 0);
+        assert_eq!(invocations, ["app a.txt", "app b.txt", "app c.txt"]);
+    }
+
+    #[test]
+    fn files_beyond_arg_max_are_launched() {
+        // Without a stack limit ARG_MAX may be too large to reach.
+        let budget = argument_budget();
+        if budget > 64 * 1024 * 1024 {
+            return;
+        }
+        // Twice as many bytes of files as ARG_MAX.
+        let file = "f".repeat(100);
+        let count = 4 * budget / argument_size(file.len());
+        let files = vec![file; count];
+        let entries = [entry("true %F")];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let commands = item_commands(&items[0], &files, &[], None, None, None).unwrap();
+        assert!(commands.len() > 2);
+        for (_, mut command) in commands {
+            assert!(command.status().unwrap().success());
+        }
+    }
+
+    #[test]
+    fn browsers_launch_without_arguments() {
+        let contents = "[Desktop Entry]\nVersion=1.0\nType=Application\nName=Firefox\n\
+            GenericName=Web Browser\nIcon=firefox\nExec=firefox %u\nTerminal=false\n\
+            MimeType=text/html;x-scheme-handler/http;x-scheme-handler/https;\n\
+            StartupNotify=true\nActions=new-window;new-private-window;\n\
+            [Desktop Action new-window]\nName=New Window\nExec=firefox --new-window %u\n\
+            [Desktop Action new-private-window]\nName=New Private Window\nExec=firefox --private-window %U\n";
+        let entries = [parse(contents, &Environment::default()).unwrap()];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let argv = |item: &MenuItem| {
+            let commands = item_commands(item, &[], &[], None, None, None).unwrap();
+            assert_eq!(commands.len(), 1);
+            let command = &commands[0].1;
+            let mut argv = vec![command.get_program().to_str().unwrap().to_owned()];
+            argv.extend(command.get_args().map(|a| a.to_str().unwrap().to_owned()));
+            argv
+        };
+        assert_eq!(argv(&items[0]), ["firefox"]);
+        assert_eq!(argv(&items[1]), ["firefox", "--new-window"]);
+        assert_eq!(argv(&items[2]), ["firefox", "--private-window"]);
+    }
+
+    #[test]
+    fn expand_strips_deprecated_codes() {
+        let expanded = expand("app %d%D%n%N%v%m", &[]);
+        assert_eq!(expanded, "app ");
+    }
+
+    #[test]
+    fn expand_literal_percent() {
+        assert_eq!(expand("app 100%%", &[]), "app 100%");
+    }
+}
@@ -0,0 +1,179 @@
+//! Display templates, which render the line of an entry from its fields.
+
+use std::borrow::Cow;
+
+use crate::entry::DesktopEntry;
+use crate::EntryType;
+
+#[derive(Debug, PartialEq)]
+enum Placeholder {
+    Name,
+    Command,
+    /// The file name of the command, which templates can't name.
+    Program,
+    Filename,
+    GenericName,
+    Comment,
+    Icon,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A display template such as `{command} - {name}`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Format(Vec<Segment>);
+
+impl Format {
+    /// Parses a template, where `{{` and `}}` stand for literal braces.
+    pub(crate) fn parse(template: &str) -> Result<Format, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(
+                                    "Unclosed { in format, use {{ for a literal brace.".to_owned()
+                                )
+                            }
+                        }
+                    }
+                    let placeholder = match name.as_str() {
+                        "name" => Placeholder::Name,
+                        "command" => Placeholder::Command,
+                        "filename" => Placeholder::Filename,
+                        "generic_name" => Placeholder::GenericName,
+                        "comment" => Placeholder::Comment,
+                        "icon" => Placeholder::Icon,
+                        _ => return Err(format!("Unknown placeholder {{{}}} in format.", name)),
+                    };
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                '}' => return Err("Unmatched } in format, use }} for a literal brace.".to_owned()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Format(segments))
+    }
+
+    /// Shows the file name of the command instead of the command, as for --command-basename.
+    pub(crate) fn command_basename(mut self) -> Format {
+        for segment in &mut self.0 {
+            if *segment == Segment::Placeholder(Placeholder::Command) {
+                *segment = Segment::Placeholder(Placeholder::Program);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn render(&self, entry: &DesktopEntry) -> String {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            rendered.push_str(&match segment {
+                Segment::Literal(literal) => Cow::Borrowed(literal.as_str()),
+                Segment::Placeholder(Placeholder::Name) => entry.field(&EntryType::Name),
+                Segment::Placeholder(Placeholder::Command) => entry.field(&EntryType::Command),
+                Segment::Placeholder(Placeholder::Program) => match entry.program() {
+                    Some(program) => Cow::Owned(program),
+                    None => entry.field(&EntryType::Command),
+                },
+                Segment::Placeholder(Placeholder::Filename) => entry.field(&EntryType::Filename),
+                Segment::Placeholder(Placeholder::GenericName) => {
+                    Cow::Borrowed(entry.generic_name.as_deref().unwrap_or_default())
+                }
+                Segment::Placeholder(Placeholder::Comment) => {
+                    Cow::Borrowed(entry.comment.as_deref().unwrap_or_default())
+                }
+                Segment::Placeholder(Placeholder::Icon) => {
+                    Cow::Borrowed(entry.icon.as_deref().unwrap_or_default())
+                }
+            });
+        }
+        rendered
+    }
+}
+
+impl From<&EntryType> for Format {
+    fn from(entry_type: &EntryType) -> Format {
+        let placeholder = match entry_type {
+            EntryType::Name => Placeholder::Name,
+            EntryType::Command => Placeholder::Command,
+            EntryType::Filename => Placeholder::Filename,
+        };
+        Format(vec![Segment::Placeholder(placeholder)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::Environment;
+    use crate::test_support::parse_as;
+
+    fn firefox() -> DesktopEntry {
+        let contents = "[Desktop Entry]\nType=Application\nName=Firefox\nGenericName=Web Browser\n\
+            Comment=Browse the Web\nIcon=firefox\nExec=firefox %u\n";
+        parse_as("firefox", contents, &Environment::default()).unwrap()
+    }
+
+    #[test]
+    fn render_placeholders() {
+        let format =
+            Format::parse("{command} — {name} ({generic_name}: {comment}) {filename} {icon}");
+        assert_eq!(
+            format.unwrap().render(&firefox()),
+            "firefox — Firefox (Web Browser: Browse the Web) firefox firefox"
+        );
+    }
+
+    #[test]
+    fn render_command_basename() {
+        let contents =
+            "[Desktop Entry]\nType=Application\nName=Firefox\nExec=/usr/lib/firefox/firefox %u\n";
+        let entry = parse_as("firefox", contents, &Environment::default()).unwrap();
+        let format = Format::parse("{command} {name}").unwrap();
+        assert_eq!(format.render(&entry), "/usr/lib/firefox/firefox Firefox");
+        assert_eq!(format.command_basename().render(&entry), "firefox Firefox");
+        let format = Format::from(&EntryType::Name).command_basename();
+        assert_eq!(format.render(&entry), "Firefox");
+    }
+
+    #[test]
+    fn render_escaped_braces() {
+        let format = Format::parse("{{{name}}} {{}}").unwrap();
+        assert_eq!(format.render(&firefox()), "{Firefox} {}");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Format::parse("{version}")
+            .unwrap_err()
+            .contains("{version}"));
+        assert!(Format::parse("{name").is_err());
+        assert!(Format::parse("name}").is_err());
+    }
+}
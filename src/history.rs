@@ -0,0 +1,264 @@
+//! The launch history in `$XDG_CACHE_HOME/dmenu-desktop/history`, which --sort frecency and
+//! --min-score rank the entries by.
+//!
+//! Each line holds the desktop file ID of a launched entry, its launch count and the time it was
+//! last launched in seconds since the Unix epoch, separated by tabs.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cache;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+#[derive(Debug, Default, PartialEq)]
+struct Usage {
+    count: u64,
+    last_used: u64,
+}
+
+/// Launch counts and last-used timestamps, keyed by desktop entry filename.
+#[derive(Debug, Default, PartialEq)]
+pub struct History {
+    usage: HashMap<String, Usage>,
+}
+
+impl History {
+    /// Loads the history file, treating a missing or corrupt file as an empty history.
+    pub fn load() -> History {
+        path()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| History::parse(&s))
+            .unwrap_or_default()
+    }
+
+    /// Parses history lines of the form `filename<TAB>count<TAB>last_used`.
+    fn parse(contents: &str) -> Option<History> {
+        let mut usage = HashMap::new();
+        for line in contents.lines().filter(|l| !l.is_empty()) {
+            let mut fields = line.split('\t');
+            let filename = fields.next()?;
+            let count = fields.next()?.parse().ok()?;
+            let last_used = fields.next()?.parse().ok()?;
+            if fields.next().is_some() {
+                return None;
+            }
+            usage.insert(filename.to_owned(), Usage { count, last_used });
+        }
+        Some(History { usage })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = path() else {
+            return Err(io::Error::other("No cache directory found."));
+        };
+        self.save_to(&path)
+    }
+
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut filenames: Vec<_> = self.usage.keys().collect();
+        filenames.sort();
+        let mut contents = String::new();
+        for filename in filenames {
+            let usage = &self.usage[filename];
+            contents.push_str(&format!(
+                "{}\t{}\t{}\n",
+                filename, usage.count, usage.last_used
+            ));
+        }
+        // Write to a temporary file first so that a run reading the history never sees part of it.
+        let temp = path.with_extension(format!("tmp{}", process::id()));
+        fs::File::create(&temp)?.write_all(contents.as_bytes())?;
+        fs::rename(temp, path)
+    }
+
+    pub fn record(&mut self, filename: &str, now: u64) {
+        let usage = self.usage.entry(filename.to_owned()).or_default();
+        usage.count += 1;
+        usage.last_used = now;
+    }
+
+    /// Removes an entry from the history, returning whether it was in it.
+    pub fn forget(&mut self, filename: &str) -> bool {
+        self.usage.remove(filename).is_some()
+    }
+
+    /// Lists the entries with their launch count and last-used time, most launched first.
+    fn list(&self) -> String {
+        let mut usage: Vec<_> = self.usage.iter().collect();
+        usage
+            .sort_by(|(a, a_usage), (b, b_usage)| b_usage.count.cmp(&a_usage.count).then(a.cmp(b)));
+        let mut list = String::new();
+        for (filename, usage) in usage {
+            list.push_str(&format!(
+                "{}\t{}\t{}\n",
+                filename,
+                usage.count,
+                format_time(usage.last_used)
+            ));
+        }
+        list
+    }
+
+    /// Returns the launch count weighted by how recently the entry was last used.
+    pub fn score(&self, filename: &str, now: u64) -> f64 {
+        let Some(usage) = self.usage.get(filename) else {
+            return 0.0;
+        };
+        let weight = match now.saturating_sub(usage.last_used) {
+            age if age < HOUR => 4.0,
+            age if age < DAY => 2.0,
+            age if age < WEEK => 1.0,
+            _ => 0.5,
+        };
+        usage.count as f64 * weight
+    }
+}
+
+/// Returns the current time as seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Runs a --history action: `list`, `clear` or `forget <ID>`.
+pub fn manage(action: &[String]) -> io::Result<()> {
+    match action {
+        [list] if list == "list" => print!("{}", History::load().list()),
+        [clear] if clear == "clear" => {
+            let Some(path) = path() else {
+                return Ok(());
+            };
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        [forget, filename] if forget == "forget" => {
+            let mut history = History::load();
+            if !history.forget(filename) {
+                return Err(io::Error::other(format!(
+                    "{} is not in the launch history.",
+                    filename
+                )));
+            }
+            history.save()?;
+        }
+        _ => {
+            return Err(io::Error::other(
+                "--history takes list, clear or forget <ID>.",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time.
+pub(crate) fn format_time(secs: u64) -> String {
+    let (days, time) = (secs / DAY, secs % DAY);
+    // Converts days to a civil date, from Howard Hinnant's chrono-compatible date algorithms.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / HOUR,
+        time % HOUR / 60,
+        time % 60
+    )
+}
+
+fn path() -> Option<PathBuf> {
+    Some(cache::dir()?.join("history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn parse_history() {
+        let history = History::parse("firefox\t3\t100\nfoot\t1\t50\n").unwrap();
+        assert_eq!(
+            history.usage["firefox"],
+            Usage {
+                count: 3,
+                last_used: 100
+            }
+        );
+        assert_eq!(
+            history.usage["foot"],
+            Usage {
+                count: 1,
+                last_used: 50
+            }
+        );
+    }
+
+    #[test]
+    fn saved_history_is_parsed_back() {
+        let dir = temp_dir("history");
+        let path = dir.join("dmenu-desktop").join("history");
+        let mut history = History::default();
+        history.record("foot", 50);
+        history.record("firefox", 100);
+        history.save_to(&path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "firefox\t1\t100\nfoot\t1\t50\n");
+        assert_eq!(History::parse(&contents), Some(history));
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_corrupt_history() {
+        assert_eq!(History::parse("firefox\tthree\t100\n"), None);
+        assert_eq!(History::parse("firefox\t3\n"), None);
+    }
+
+    #[test]
+    fn list_and_forget() {
+        let mut history =
+            History::parse("foot\t1\t0\nfirefox\t3\t1700000000\ngimp\t1\t951782400\n").unwrap();
+        assert_eq!(
+            history.list(),
+            "firefox\t3\t2023-11-14 22:13:20 UTC\nfoot\t1\t1970-01-01 00:00:00 UTC\n\
+            gimp\t1\t2000-02-29 00:00:00 UTC\n"
+        );
+        assert!(history.forget("foot"));
+        assert!(!history.forget("foot"));
+        assert_eq!(history.usage.len(), 2);
+    }
+
+    #[test]
+    fn recent_launches_score_higher() {
+        let mut history = History::default();
+        history.record("old", 0);
+        history.record("old", 0);
+        history.record("new", 10 * WEEK);
+        let now = 10 * WEEK + 1;
+        assert!(history.score("new", now) > history.score("old", now));
+        assert_eq!(history.score("unknown", now), 0.0);
+    }
+}
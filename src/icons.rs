@@ -0,0 +1,216 @@
+//! Finding the file of an icon for --resolve-icons, following the lookup of the icon theme
+//! specification without scaled directories.
+//!
+//! The icon theme is --icon-theme or the one of the GTK settings, looked up before the themes it
+//! inherits from and hicolor. Within a theme the directories with the size closest to 48 pixels
+//! come first, and `/usr/share/pixmaps` is looked in last.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ini::Ini;
+
+use crate::discovery::data_dirs;
+use crate::{config, Cli};
+
+/// The size icons are looked up at, in pixels.
+const SIZE: u32 = 48;
+
+const EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+/// Ordered directories of icon files, with the names of the files in them.
+pub(crate) struct IconLookup {
+    dirs: Vec<(PathBuf, HashSet<String>)>,
+}
+
+impl IconLookup {
+    /// Lists the directories of `theme` and the themes it inherits from in the base directories,
+    /// followed by the pixmaps directory.
+    pub(crate) fn new(theme: &str, base_dirs: &[PathBuf], pixmaps: &Path) -> IconLookup {
+        let mut themes = Vec::new();
+        add_theme(theme, base_dirs, &mut themes);
+        add_theme("hicolor", base_dirs, &mut themes);
+
+        let mut dirs = Vec::new();
+        for (name, mut subdirs) in themes {
+            subdirs.sort_by_key(|(_, distance)| *distance);
+            for (subdir, _) in subdirs {
+                for base_dir in base_dirs {
+                    let dir = base_dir.join(&name).join(&subdir);
+                    if let Some(files) = list_files(&dir) {
+                        dirs.push((dir, files));
+                    }
+                }
+            }
+        }
+        if let Some(files) = list_files(pixmaps) {
+            dirs.push((pixmaps.to_owned(), files));
+        }
+        IconLookup { dirs }
+    }
+
+    /// Returns the file of an icon name, or the icon itself when it is an existing absolute path.
+    pub(crate) fn find(&self, icon: &str) -> Option<PathBuf> {
+        if Path::new(icon).is_absolute() {
+            return Some(PathBuf::from(icon)).filter(|p| p.exists());
+        }
+        // Some Icon keys name the file, extension included, as older entries did.
+        let has_extension = Path::new(icon)
+            .extension()
+            .is_some_and(|e| EXTENSIONS.iter().any(|ext| e == *ext));
+        let names: Vec<String> = EXTENSIONS
+            .iter()
+            .map(|ext| format!("{}.{}", icon, ext))
+            .chain(has_extension.then(|| icon.to_owned()))
+            .collect();
+        self.dirs.iter().find_map(|(dir, files)| {
+            let name = names.iter().find(|name| files.contains(*name))?;
+            Some(dir.join(name))
+        })
+    }
+}
+
+/// Lists the icons of --icon-theme or the GTK theme in `~/.icons` and the XDG data directories.
+pub(crate) fn lookup(cli: &Cli) -> IconLookup {
+    let theme = cli.icon_theme.clone().or_else(gtk_theme);
+    let base_dirs: Vec<PathBuf> = config::home()
+        .map(|home| home.join(".icons"))
+        .into_iter()
+        .chain(data_dirs(cli).iter().map(|dir| dir.join("icons")))
+        .collect();
+    IconLookup::new(
+        theme.as_deref().unwrap_or("hicolor"),
+        &base_dirs,
+        Path::new("/usr/share/pixmaps"),
+    )
+}
+
+/// Returns the icon theme of the GTK settings.
+fn gtk_theme() -> Option<String> {
+    let config_home = config::config_home()?;
+    ["gtk-4.0", "gtk-3.0"].iter().find_map(|version| {
+        let ini = Ini::load_from_file(config_home.join(version).join("settings.ini")).ok()?;
+        let theme = ini.get_from(Some("Settings"), "gtk-icon-theme-name")?;
+        Some(theme.to_owned()).filter(|t| !t.is_empty())
+    })
+}
+
+/// Appends a theme with its subdirectories and their distance from `SIZE`, then the themes it
+/// inherits from, skipping themes already added and themes without an index.
+fn add_theme(name: &str, base_dirs: &[PathBuf], themes: &mut Vec<(String, Vec<(String, u32)>)>) {
+    if themes.iter().any(|(added, _)| added == name) {
+        return;
+    }
+    let Some(index) = base_dirs
+        .iter()
+        .find_map(|dir| Ini::load_from_file(dir.join(name).join("index.theme")).ok())
+    else {
+        return;
+    };
+    let Some(theme) = index.section(Some("Icon Theme")) else {
+        return;
+    };
+    let subdirs = split(theme.get("Directories"))
+        .filter_map(|subdir| Some((subdir.to_owned(), distance(index.section(Some(subdir))?))))
+        .collect();
+    themes.push((name.to_owned(), subdirs));
+    for parent in split(theme.get("Inherits")) {
+        add_theme(parent, base_dirs, themes);
+    }
+}
+
+fn split(list: Option<&str>) -> impl Iterator<Item = &str> {
+    list.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+/// Returns how far the icons of a theme directory are from `SIZE`, 0 when they can be shown at it.
+fn distance(subdir: &ini::Properties) -> u32 {
+    let number = |key: &str| subdir.get(key).and_then(|v| v.trim().parse::<u32>().ok());
+    let size = number("Size").unwrap_or(SIZE);
+    let (min, max) = match subdir.get("Type") {
+        Some("Fixed") => (size, size),
+        Some("Scalable") => (
+            number("MinSize").unwrap_or(size),
+            number("MaxSize").unwrap_or(size),
+        ),
+        _ => {
+            let threshold = number("Threshold").unwrap_or(2);
+            (size.saturating_sub(threshold), size + threshold)
+        }
+    };
+    min.saturating_sub(SIZE) + SIZE.saturating_sub(max)
+}
+
+fn list_files(dir: &Path) -> Option<HashSet<String>> {
+    let files = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    Some(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_dir, write};
+
+    #[test]
+    fn theme_lookup() {
+        let root = temp_dir("icons");
+        let icons = root.join("icons");
+        write(
+            &icons.join("Papirus/index.theme"),
+            "[Icon Theme]\nInherits=Adwaita\nDirectories=16x16/apps,64x64/apps\n\
+             [16x16/apps]\nSize=16\nType=Fixed\n[64x64/apps]\nSize=64\nType=Fixed\n",
+        );
+        write(
+            &icons.join("Adwaita/index.theme"),
+            "[Icon Theme]\nDirectories=scalable/apps\n\
+             [scalable/apps]\nSize=16\nMinSize=8\nMaxSize=512\nType=Scalable\n",
+        );
+        write(
+            &icons.join("hicolor/index.theme"),
+            "[Icon Theme]\nDirectories=48x48/apps\n[48x48/apps]\nSize=48\n",
+        );
+        for file in [
+            "Papirus/16x16/apps/firefox.svg",
+            "Papirus/64x64/apps/firefox.svg",
+            "Adwaita/scalable/apps/foot.svg",
+            "hicolor/48x48/apps/foot.png",
+            "hicolor/48x48/apps/htop.png",
+        ] {
+            write(&icons.join(file), "");
+        }
+        write(&root.join("pixmaps/xterm.xpm"), "");
+
+        let lookup = IconLookup::new(
+            "Papirus",
+            std::slice::from_ref(&icons),
+            &root.join("pixmaps"),
+        );
+        let found = |icon: &str| lookup.find(icon);
+        assert_eq!(
+            found("firefox"),
+            Some(icons.join("Papirus/64x64/apps/firefox.svg"))
+        );
+        assert_eq!(
+            found("foot"),
+            Some(icons.join("Adwaita/scalable/apps/foot.svg"))
+        );
+        assert_eq!(
+            found("htop"),
+            Some(icons.join("hicolor/48x48/apps/htop.png"))
+        );
+        assert_eq!(found("xterm"), Some(root.join("pixmaps/xterm.xpm")));
+        assert_eq!(found("xterm.xpm"), Some(root.join("pixmaps/xterm.xpm")));
+        assert_eq!(found("missing"), None);
+        let absolute = root.join("pixmaps/xterm.xpm");
+        assert_eq!(found(absolute.to_str().unwrap()), Some(absolute.clone()));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
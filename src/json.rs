@@ -0,0 +1,194 @@
+//! The entries printed by --output json.
+
+use std::fmt::Write;
+
+use crate::entry::DesktopEntry;
+
+/// Serializes entries as a JSON array of objects, one object per line.
+pub(crate) fn to_json<'a>(entries: impl Iterator<Item = &'a DesktopEntry>) -> String {
+    let objects: Vec<String> = entries
+        .map(|entry| {
+            let path = match &entry.path {
+                Some(path) => string(&path.to_string_lossy()),
+                None => "null".to_owned(),
+            };
+            let icon = match &entry.icon {
+                Some(icon) => string(icon),
+                None => "null".to_owned(),
+            };
+            format!(
+                "{{\"name\":{},\"exec\":{},\"filename\":{},\"terminal\":{},\"path\":{},\"hide\":{},\"icon\":{}}}",
+                string(&entry.name),
+                string(&entry.exec),
+                string(&entry.filename),
+                entry.terminal,
+                path,
+                entry.hide(),
+                icon,
+            )
+        })
+        .collect();
+    match objects.is_empty() {
+        true => "[]\n".to_owned(),
+        false => format!("[\n  {}\n]\n", objects.join(",\n  ")),
+    }
+}
+
+fn string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::iter::Peekable;
+    use std::str::Chars;
+
+    use super::*;
+    use crate::entry::Environment;
+    use crate::test_support::parse_as;
+
+    #[derive(Debug, PartialEq)]
+    enum Value {
+        Null,
+        Bool(bool),
+        String(String),
+    }
+
+    /// Parses the subset of JSON produced by `to_json`.
+    fn parse(json: &str) -> Vec<HashMap<String, Value>> {
+        let mut chars = json.chars().peekable();
+        let mut objects = Vec::new();
+        expect(&mut chars, '[');
+        while skip_whitespace(&mut chars) == Some('{') {
+            chars.next();
+            let mut object = HashMap::new();
+            while skip_whitespace(&mut chars) == Some('"') {
+                let key = parse_string(&mut chars);
+                expect(&mut chars, ':');
+                let value = match skip_whitespace(&mut chars) {
+                    Some('"') => Value::String(parse_string(&mut chars)),
+                    _ => {
+                        let word: String =
+                            std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_alphabetic()))
+                                .collect();
+                        match word.as_str() {
+                            "null" => Value::Null,
+                            "true" => Value::Bool(true),
+                            "false" => Value::Bool(false),
+                            _ => panic!("unexpected value {word}"),
+                        }
+                    }
+                };
+                object.insert(key, value);
+                if skip_whitespace(&mut chars) == Some(',') {
+                    chars.next();
+                }
+            }
+            expect(&mut chars, '}');
+            objects.push(object);
+            if skip_whitespace(&mut chars) == Some(',') {
+                chars.next();
+            }
+        }
+        expect(&mut chars, ']');
+        objects
+    }
+
+    fn skip_whitespace(chars: &mut Peekable<Chars>) -> Option<char> {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        chars.peek().copied()
+    }
+
+    fn expect(chars: &mut Peekable<Chars>, expected: char) {
+        assert_eq!(skip_whitespace(chars), Some(expected));
+        chars.next();
+    }
+
+    fn parse_string(chars: &mut Peekable<Chars>) -> String {
+        expect(chars, '"');
+        let mut s = String::new();
+        loop {
+            match chars.next().unwrap() {
+                '"' => return s,
+                '\\' => match chars.next().unwrap() {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let code: String = chars.by_ref().take(4).collect();
+                        let code = u32::from_str_radix(&code, 16).unwrap();
+                        s.push(char::from_u32(code).unwrap());
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn entry(filename: &str, keys: &str) -> DesktopEntry {
+        let contents = format!("[Desktop Entry]\nType=Application\n{keys}");
+        parse_as(filename, &contents, &Environment::default()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut tricky = entry("tricky", "Name=x\nExec=x\nPath=/tmp\n");
+        tricky.name = "Say \"hi\"\nnow\t\u{1}".to_owned();
+        tricky.exec = "sh -c 'echo \"\\\\\"'".to_owned();
+        let entries = [
+            entry("firefox", "Name=Firefox\nExec=firefox %u\nIcon=firefox\n"),
+            entry(
+                "htop",
+                "Name=Htop\nExec=htop\nTerminal=true\nNoDisplay=true\n",
+            ),
+            tricky,
+        ];
+
+        let parsed = parse(&to_json(entries.iter()));
+        assert_eq!(parsed.len(), entries.len());
+        for (object, entry) in parsed.iter().zip(&entries) {
+            let path = match &entry.path {
+                Some(path) => Value::String(path.to_string_lossy().into_owned()),
+                None => Value::Null,
+            };
+            let icon = match &entry.icon {
+                Some(icon) => Value::String(icon.clone()),
+                None => Value::Null,
+            };
+            let expected = HashMap::from([
+                ("name".to_owned(), Value::String(entry.name.clone())),
+                ("exec".to_owned(), Value::String(entry.exec.clone())),
+                ("filename".to_owned(), Value::String(entry.filename.clone())),
+                ("terminal".to_owned(), Value::Bool(entry.terminal)),
+                ("path".to_owned(), path),
+                ("hide".to_owned(), Value::Bool(entry.hide())),
+                ("icon".to_owned(), icon),
+            ]);
+            assert_eq!(object, &expected);
+        }
+    }
+
+    #[test]
+    fn empty_array() {
+        assert_eq!(to_json(std::iter::empty()), "[]\n");
+        assert!(parse(&to_json(std::iter::empty())).is_empty());
+    }
+}
@@ -0,0 +1,1292 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use blocklist::Blocklist;
+use discovery::{application_dirs, describe_dirs, read_entries};
+use entry::{DesktopEntry, Environment, HideReason};
+pub use error::Error;
+use exec::{launch, launch_item};
+use format::Format;
+use history::History;
+use icons::IconLookup;
+use menu::{find_item, menu_items, truncate_lines, MenuItem};
+use overrides::Overrides;
+
+mod blocklist;
+mod cache;
+mod collate;
+mod config;
+mod daemon;
+mod dbus;
+mod discovery;
+mod entry;
+mod error;
+mod exec;
+mod format;
+mod history;
+mod icons;
+mod json;
+mod menu;
+mod overrides;
+mod startup;
+mod terminal;
+#[cfg(test)]
+mod test_support;
+mod validate;
+
+#[derive(ValueEnum, Clone, Debug)]
+enum EntryType {
+    Name,
+    Command,
+    Filename,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Output {
+    Plain,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum DedupBy {
+    Stem,
+    Name,
+    Exec,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Scope {
+    Systemd,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Sort {
+    Alpha,
+    Natural,
+    Frecency,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum SortField {
+    Name,
+    Exec,
+    Filename,
+    /// The modification time of the desktop file, oldest first.
+    Mtime,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Report every problem of a desktop file that would hide or skip it and exit, with 1 when it would not be listed
+    Validate {
+        /// The desktop file to check
+        path: PathBuf,
+    },
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
+struct Cli {
+    /// Field shown and matched, shorthand for --display and --match
+    #[arg(long, default_value = "name")]
+    entry_type: EntryType,
+
+    /// Field shown in the menu, overrides --entry-type
+    #[arg(long, value_name = "ENTRY_TYPE")]
+    display: Option<EntryType>,
+
+    /// Show and match only the file name of the program for the command field and {command}
+    #[arg(long)]
+    command_basename: bool,
+
+    /// Field a typed selection is also looked up by when it matches no shown line, overrides --entry-type
+    #[arg(long = "match", id = "match", value_name = "ENTRY_TYPE")]
+    match_by: Option<EntryType>,
+
+    /// Template for each line, overrides --entry-type. Supports {name}, {command}, {filename}, {generic_name}, {comment} and {icon}, use {{ and }} for literal braces
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Determines the command used to invoke dmenu or an equivalent.
+    #[arg(long)]
+    dmenu: Option<String>,
+
+    /// Argument appended to the dmenu command as is, without splitting or unquoting, can be repeated
+    #[arg(long, value_name = "ARG", allow_hyphen_values = true)]
+    dmenu_arg: Vec<String>,
+
+    /// Prompt passed to the menu after --prompt-flag
+    #[arg(long, value_name = "TEXT")]
+    prompt: Option<String>,
+
+    /// Option of the menu taking the prompt, such as --prompt for fuzzel
+    #[arg(
+        long,
+        value_name = "FLAG",
+        default_value = "-p",
+        allow_hyphen_values = true
+    )]
+    prompt_flag: String,
+
+    /// Terminal emulator used to launch terminal applications, run with --terminal-exec-arg and the command. A {} in it is replaced by the command instead
+    #[arg(long)]
+    terminal: Option<String>,
+
+    /// Launch every entry in --terminal, not only the ones with Terminal=true
+    #[arg(long, requires = "terminal")]
+    terminal_always: bool,
+
+    /// Argument after which --terminal takes the command to run, can be empty
+    #[arg(
+        long,
+        value_name = "ARG",
+        default_value = "-e",
+        allow_hyphen_values = true
+    )]
+    terminal_exec_arg: String,
+
+    /// Launch entries in their own transient scope, systemd runs them with systemd-run --user --scope when it is installed
+    #[arg(long)]
+    scope: Option<Scope>,
+
+    /// Keep launched applications in this session with its stdin, stdout and stderr instead of detaching them
+    #[arg(long)]
+    no_detach: bool,
+
+    /// Append the output of launched applications to this file, after a line naming the entry, instead of discarding it
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Launch applications through $SHELL -lc, for the PATH and variables set up by the login shell
+    #[arg(long)]
+    shell_wrap: bool,
+
+    /// List Type=Link entries too, opening their URL with --url-opener
+    #[arg(long)]
+    include_links: bool,
+
+    /// Command opening the URL of a Type=Link entry, run with the URL as its last argument
+    #[arg(long, value_name = "COMMAND", default_value = "xdg-open")]
+    url_opener: String,
+
+    /// Run the selection as a command when it matches no entry
+    #[arg(long)]
+    exec_unmatched: bool,
+
+    /// With --exec-unmatched, launch a selection starting with PREFIX in the terminal without it, such as !htop with !, rather than printing its output
+    #[arg(long, value_name = "PREFIX", requires = "exec_unmatched")]
+    terminal_prefix: Option<String>,
+
+    /// Launch the only shown entry starting with a selection that matches no entry, ignoring case
+    #[arg(long)]
+    match_prefix: bool,
+
+    /// Match selections against the shown lines ignoring case, when a single entry matches that way
+    #[arg(long)]
+    ignore_case: bool,
+
+    /// Launch an entry whose line is followed by arguments in the selection, such as "Firefox --private-window", with the arguments appended to its Exec key
+    #[arg(long)]
+    allow_append: bool,
+
+    /// Don't look for an installed terminal emulator when --terminal is omitted
+    #[arg(long)]
+    no_auto_terminal: bool,
+
+    /// Comma-separated terminal emulators to probe when --terminal is omitted, tried after $TERMINAL
+    #[arg(long, value_delimiter = ',', value_name = "TERMINALS")]
+    terminal_order: Vec<String>,
+
+    /// Order of the listed entries, natural is alphabetical with the numbers in names and commands compared by value and frecency puts frequently and recently launched entries first.
+    #[arg(long, default_value = "alpha")]
+    sort: Sort,
+
+    /// Leave entries with a frecency score below N out of the menu, they can still be typed or given to --select. Entries never launched are still listed
+    #[arg(long, value_name = "N")]
+    min_score: Option<f64>,
+
+    /// Leave the entries that were never launched out of the menu as well
+    #[arg(long, requires = "min_score")]
+    hide_new: bool,
+
+    /// Field the entries are sorted by, before --sort frecency puts the most used first
+    #[arg(long, default_value = "name")]
+    sort_field: SortField,
+
+    /// Reverse the order given by --sort, before --count keeps the first lines
+    #[arg(long)]
+    reverse: bool,
+
+    /// Group the entries under their first category, with a "--- Category ---" line above each group that can't be selected
+    #[arg(long)]
+    group_by_category: bool,
+
+    /// Colon-separated desktop names used for OnlyShowIn and NotShowIn, overrides $XDG_CURRENT_DESKTOP and the desktop inferred from the session when it is unset
+    #[arg(long)]
+    desktop: Option<String>,
+
+    /// Collapse entries sharing this key to the first one found, where stem is the desktop file ID. Hidden entries are only collapsed by stem
+    #[arg(long, default_value = "stem")]
+    dedup_by: DedupBy,
+
+    /// Additional directory to search for desktop entries, can be repeated. A leading ~ is the home directory and * and ? are globs, such as ~/projects/*/applications
+    #[arg(long, value_name = "PATH")]
+    include_dir: Vec<PathBuf>,
+
+    /// Directory used instead of $XDG_DATA_HOME
+    #[arg(long, value_name = "PATH")]
+    data_home: Option<PathBuf>,
+
+    /// Colon-separated directories used instead of $XDG_DATA_DIRS
+    #[arg(long, value_name = "PATHS")]
+    data_dirs: Option<OsString>,
+
+    /// Search the --include-dir directories before the XDG directories
+    #[arg(long)]
+    prepend_dirs: bool,
+
+    /// Manage the launch history and exit: list, clear, or forget ID
+    #[arg(long, num_args = 1..=2, value_names = ["ACTION", "ID"])]
+    history: Vec<String>,
+
+    /// Print the time spent finding the directories, reading the entries and sorting them to stderr, with the entry count, before showing the menu
+    #[arg(long)]
+    time_startup: bool,
+
+    /// Print the environment, directories and entry counts dmenu-desktop sees, for bug reports, and exit
+    #[arg(long)]
+    env_info: bool,
+
+    /// Launch the entry with this desktop file ID, or the field given with --select-by, without showing the menu
+    #[arg(long, value_name = "VALUE")]
+    select: Option<String>,
+
+    /// Field --select looks entries up by
+    #[arg(long, value_name = "ENTRY_TYPE", default_value = "filename")]
+    select_by: EntryType,
+
+    /// Print the sorted and deduplicated program names of the entries' Exec keys and exit, ignoring --dmenu
+    #[arg(long)]
+    print_commands: bool,
+
+    /// Print the application directories in search order, marking the missing ones, and exit
+    #[arg(long)]
+    list_dirs: bool,
+
+    /// Append the Comment of each entry to its line, after --separator
+    #[arg(long)]
+    with_comment: bool,
+
+    /// Text between the line and the comment with --with-comment, a tab by default
+    #[arg(
+        long,
+        value_name = "STR",
+        default_value = "\t",
+        hide_default_value = true
+    )]
+    separator: String,
+
+    /// File of desktop file IDs or globs to hide, one per line, instead of $XDG_CONFIG_HOME/dmenu-desktop/blocklist
+    #[arg(long, value_name = "PATH")]
+    blocklist: Option<PathBuf>,
+
+    /// File replacing the Exec and Name keys of entries by desktop file ID, instead of $XDG_CONFIG_HOME/dmenu-desktop/overrides
+    #[arg(long, value_name = "PATH")]
+    overrides: Option<PathBuf>,
+
+    /// Show the entry with this desktop file ID even if it is hidden or blocked, can be repeated
+    #[arg(long, value_name = "ID")]
+    force_show: Vec<String>,
+
+    /// Hide the entry with this desktop file ID, can be repeated
+    #[arg(long, value_name = "ID")]
+    force_hide: Vec<String>,
+
+    /// List hidden entries too, marked with a "[hidden] " prefix
+    #[arg(long)]
+    show_all: bool,
+
+    /// Print why desktop files are skipped or hidden to stderr
+    #[arg(long)]
+    verbose: bool,
+
+    /// Parse every desktop file without reading or writing the entry cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Give each line its icon in the format read by `rofi -dmenu -show-icons` and `fuzzel -d`
+    #[arg(long, visible_alias = "with-icons", conflicts_with = "print0")]
+    rofi_icons: bool,
+
+    /// Like --rofi-icons with the path of the icon file instead of the icon name, for menus that don't look icons up in the icon theme
+    #[arg(long, conflicts_with = "print0")]
+    resolve_icons: bool,
+
+    /// Icon theme looked in by --resolve-icons, instead of the GTK icon theme
+    #[arg(long, value_name = "NAME")]
+    icon_theme: Option<String>,
+
+    /// Separate lines with NUL instead of newline, the menu must be configured to read and print NUL-separated lines (e.g. `fzf --read0 --print0`)
+    #[arg(long)]
+    print0: bool,
+
+    /// Format of the entry list printed when dmenu is not provided
+    #[arg(long, default_value = "plain")]
+    output: Output,
+
+    /// Only list entries in this category, can be repeated to list entries in any of them
+    #[arg(long)]
+    category: Vec<String>,
+
+    /// Only list entries whose Implements key lists this interface, can be repeated to list entries implementing any of them
+    #[arg(long, value_name = "INTERFACE")]
+    implements: Vec<String>,
+
+    /// Keep the entries in memory and show the menu whenever a --client connects
+    #[arg(long, conflicts_with = "client")]
+    daemon: bool,
+
+    /// Ask the running --daemon to show the menu with its own options, taking no other options
+    #[arg(long, exclusive = true)]
+    client: bool,
+
+    /// Print the commands that would be run as shell command lines instead of running them, DBusActivatable entries print their Exec key
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print only the command line of the selection on one line of stdout instead of running it, for piping into another launcher such as swaymsg exec. Unlike --dry-run, commands run once per file are joined with &
+    #[arg(long, conflicts_with_all = ["dry_run", "verbose"])]
+    output_selected: bool,
+
+    /// Always launch the Exec key, even for entries with DBusActivatable=true
+    #[arg(long)]
+    no_dbus: bool,
+
+    /// Don't give startup IDs to applications with StartupNotify=true
+    #[arg(long)]
+    no_startup_notify: bool,
+
+    /// Only list lines containing this text, ignoring case, before showing them in dmenu
+    #[arg(long, value_name = "QUERY")]
+    filter: Option<String>,
+
+    /// List at most N lines after sorting and filtering, 0 for no limit. Selections are still matched against every entry
+    #[arg(long, value_name = "N")]
+    count: Option<usize>,
+
+    /// Truncate lines to N characters, ending them with an ellipsis, 0 for no limit. A truncated line still selects its entry, and lines that would be truncated alike are left whole
+    #[arg(long, value_name = "N")]
+    max_line_length: Option<usize>,
+
+    /// Only list entries that handle this MIME type
+    #[arg(long, value_name = "TYPE")]
+    for_mime: Option<String>,
+
+    /// Read the lines to show from stdin instead of listing every entry, or launch the first line directly when dmenu is not provided
+    #[arg(long)]
+    stdin: bool,
+
+    /// Files or URLs to open with the selected application, requires dmenu
+    args: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+/// Runs dmenu-desktop with the options of the config file and the command line.
+pub fn run() -> Result<(), Error> {
+    let cli = config::parse_cli();
+    let format = match &cli.format {
+        Some(template) => Format::parse(template).map_err(Error::Usage)?,
+        None => Format::from(cli.display.as_ref().unwrap_or(&cli.entry_type)),
+    };
+    let format = cli.with_command_basename(format);
+    if let (Output::Json, Some(_)) = (&cli.output, &cli.dmenu) {
+        return Err(Error::Usage(
+            "--output json cannot be used with --dmenu.".to_owned(),
+        ));
+    }
+    if !cli.args.is_empty() && cli.dmenu.is_none() && !cli.stdin {
+        return Err(Error::Usage(
+            "Opening files requires --dmenu or --stdin.".to_owned(),
+        ));
+    }
+    if !cli.history.is_empty() {
+        return Ok(history::manage(&cli.history)?);
+    }
+    if cli.list_dirs {
+        let app_dirs = application_dirs(&cli);
+        print!("{}", describe_dirs(&app_dirs));
+        return Ok(());
+    }
+    if cli.client {
+        return match daemon::client()? {
+            0 => Ok(()),
+            code => Err(Error::Exited(code)),
+        };
+    }
+
+    let environment = Environment {
+        include_links: cli.include_links,
+        ..Environment::new(cli.desktop.as_deref())
+    };
+    if let Some(Commands::Validate { path }) = &cli.command {
+        return validate::run(path, &environment);
+    }
+    let started = Instant::now();
+    let app_dirs = application_dirs(&cli);
+    let discovered = Instant::now();
+    if cli.daemon {
+        if cli.dmenu.is_none() {
+            return Err(Error::Usage("--daemon requires --dmenu.".to_owned()));
+        }
+        return Ok(daemon::serve(&cli, &format, &app_dirs)?);
+    }
+
+    let mut entries: Vec<DesktopEntry> = read_entries(
+        &app_dirs,
+        &environment,
+        &cli.dedup_by,
+        !cli.no_cache,
+        cli.verbose,
+    )
+    .into_values()
+    .collect();
+    let parsed = Instant::now();
+    Overrides::load(cli.overrides.as_deref()).apply(&mut entries, cli.verbose);
+    force_visibility(&cli, &mut entries);
+    if let Some(value) = &cli.select {
+        let entry = select(&cli, &entries, value)?;
+        let items = menu_items(std::slice::from_ref(entry), &format);
+        return launch_item(&cli, &items[0], &[]);
+    }
+    if cli.print_commands {
+        for command in commands(&cli, &entries) {
+            println!("{}", command);
+        }
+        return Ok(());
+    }
+    if cli.env_info {
+        print!("{}", env_info(&environment, &app_dirs, &entries));
+        return Ok(());
+    }
+    sort_entries(&cli, &mut entries);
+    if cli.time_startup {
+        let phases = [
+            ("Discovery", discovered - started),
+            ("Parsing", parsed - discovered),
+            ("Sorting", parsed.elapsed()),
+        ];
+        eprint!("{}", startup_times(&phases, entries.len()));
+    }
+    show_menu(&cli, &format, &entries)
+}
+
+/// Describes the time spent in each phase of --time-startup.
+fn startup_times(phases: &[(&str, Duration)], entry_count: usize) -> String {
+    let mut times = String::new();
+    for (phase, time) in phases {
+        times.push_str(&format!("{}: {:.2?}\n", phase, time));
+    }
+    let total: Duration = phases.iter().map(|(_, time)| *time).sum();
+    times.push_str(&format!("Total: {:.2?}\nEntries: {}\n", total, entry_count));
+    times
+}
+
+impl Cli {
+    /// Applies --command-basename to a format.
+    fn with_command_basename(&self, format: Format) -> Format {
+        match self.command_basename {
+            true => format.command_basename(),
+            false => format,
+        }
+    }
+
+    /// Returns the field selections are looked up by besides the shown lines, which is only needed
+    /// when the shown lines are not made of --entry-type alone.
+    fn match_type(&self) -> Option<&EntryType> {
+        let shown_differs = self.display.is_some() || self.format.is_some();
+        self.match_by
+            .as_ref()
+            .or(Some(&self.entry_type).filter(|_| shown_differs))
+    }
+
+    /// Returns whether the commands are printed rather than run.
+    fn is_dry_run(&self) -> bool {
+        self.dry_run || self.output_selected
+    }
+
+    /// Returns the --max-line-length lines are truncated to, if any.
+    fn line_limit(&self) -> Option<usize> {
+        self.max_line_length.filter(|n| *n > 0)
+    }
+}
+
+/// Finds the single entry whose --select-by field is `value`, preferring shown entries.
+fn select<'a>(
+    cli: &Cli,
+    entries: &'a [DesktopEntry],
+    value: &str,
+) -> Result<&'a DesktopEntry, Error> {
+    let matching: Vec<&DesktopEntry> = entries
+        .iter()
+        .filter(|e| e.field(&cli.select_by) == value)
+        .collect();
+    let shown: Vec<&DesktopEntry> = matching.iter().copied().filter(|e| !e.hide()).collect();
+    let candidates = if shown.is_empty() { matching } else { shown };
+    match candidates[..] {
+        [] => Err(Error::NoSuchEntry(value.to_owned())),
+        [entry] => Ok(entry),
+        _ => {
+            let mut ids: Vec<String> = candidates.iter().map(|e| e.filename.clone()).collect();
+            ids.sort();
+            Err(Error::Ambiguous(value.to_owned(), ids))
+        }
+    }
+}
+
+/// Returns the program names of the shown entries, or of every entry with --show-all, sorted and
+/// without duplicates.
+fn commands(cli: &Cli, entries: &[DesktopEntry]) -> BTreeSet<String> {
+    entries
+        .iter()
+        .filter(|e| cli.show_all || !e.hide())
+        .filter_map(DesktopEntry::program)
+        .collect()
+}
+
+/// Describes what affects the listed entries.
+fn env_info(environment: &Environment, app_dirs: &[PathBuf], entries: &[DesktopEntry]) -> String {
+    let mut info = format!("dmenu-desktop {}\n", env!("CARGO_PKG_VERSION"));
+    for var in [
+        "XDG_DATA_HOME",
+        "XDG_DATA_DIRS",
+        "XDG_CURRENT_DESKTOP",
+        "XDG_SESSION_DESKTOP",
+        "DESKTOP_SESSION",
+        "XDG_CONFIG_HOME",
+        "XDG_CACHE_HOME",
+        "LANG",
+        "LC_ALL",
+        "LC_MESSAGES",
+    ] {
+        let value = env::var_os(var).map(|v| v.to_string_lossy().into_owned());
+        info.push_str(&format!(
+            "{}={}\n",
+            var,
+            value.as_deref().unwrap_or("(unset)")
+        ));
+    }
+    info.push_str(&format!("Desktops: {}\n", environment.desktops.join(":")));
+    info.push_str(&format!("Locales: {}\n", environment.locales.join(", ")));
+    info.push_str("Application directories:\n");
+    for line in describe_dirs(app_dirs).lines() {
+        info.push_str(&format!("  {}\n", line));
+    }
+    let hidden = entries.iter().filter(|e| e.hide()).count();
+    info.push_str(&format!("Entries: {} ({} hidden)\n", entries.len(), hidden));
+    // Every feature is always built in, there are no optional Cargo features.
+    info.push_str("Features: cache, daemon, dbus, startup-notification\n");
+    info
+}
+
+/// Applies the blocklist, then --force-show and --force-hide, where hiding wins for entries given to
+/// both.
+fn force_visibility(cli: &Cli, entries: &mut [DesktopEntry]) {
+    let blocklist = Blocklist::load(cli.blocklist.as_deref());
+    for entry in entries {
+        if blocklist.blocks(&entry.filename) {
+            entry.hidden = Some(HideReason::Blocked);
+        }
+        if cli.force_show.contains(&entry.filename) {
+            entry.hidden = None;
+        }
+        if cli.force_hide.contains(&entry.filename) {
+            entry.hidden = Some(HideReason::Forced);
+        }
+    }
+}
+
+fn sort_entries(cli: &Cli, entries: &mut [DesktopEntry]) {
+    // The sorts are stable, leaving entries with the same name in filename order, and entries with
+    // the same field in name order.
+    let sort_by_text =
+        |entries: &mut [DesktopEntry], text: fn(&DesktopEntry) -> &str| match cli.sort {
+            Sort::Natural => entries.sort_by_cached_key(|e| collate::natural_key(text(e))),
+            _ => entries.sort_by_cached_key(|e| collate::key(text(e))),
+        };
+    entries.sort_by(|a, b| a.filename.cmp(&b.filename));
+    sort_by_text(entries, |e| &e.name);
+    match cli.sort_field {
+        SortField::Name => {}
+        SortField::Exec => sort_by_text(entries, |e| &e.exec),
+        SortField::Filename => entries.sort_by(|a, b| a.filename.cmp(&b.filename)),
+        SortField::Mtime => entries.sort_by_key(|e| e.mtime),
+    }
+    if let Sort::Frecency = cli.sort {
+        let history = History::load();
+        let now = history::now();
+        entries.sort_by(|a, b| {
+            let a = history.score(&a.filename, now);
+            history.score(&b.filename, now).total_cmp(&a)
+        });
+    }
+    if cli.group_by_category {
+        // The sort is stable, keeping the order given by --sort within each group.
+        entries.sort_by_cached_key(|e| collate::key(e.primary_category()));
+    }
+    if cli.reverse {
+        entries.reverse();
+    }
+}
+
+/// Prints the sorted entries, or shows them in dmenu and launches the selection when dmenu is
+/// provided.
+fn show_menu(cli: &Cli, format: &Format, entries: &[DesktopEntry]) -> Result<(), Error> {
+    let mut mime_type = cli.for_mime.as_ref();
+    if let Some(mime) = mime_type.filter(|m| !entries.iter().any(|e| e.mime_types.contains(m))) {
+        eprintln!("No entries handle {}, listing all entries.", mime);
+        mime_type = None;
+    }
+    let history = cli.min_score.map(|_| History::load());
+    let now = history::now();
+    let listed = |entry: &DesktopEntry| {
+        (cli.show_all || !entry.hide())
+            && (cli.category.is_empty()
+                || entry.categories.iter().any(|c| cli.category.contains(c)))
+            && (cli.implements.is_empty()
+                || entry.implements.iter().any(|i| cli.implements.contains(i)))
+            && mime_type.is_none_or(|m| entry.mime_types.contains(m))
+    };
+    let frequent = |entry: &DesktopEntry| {
+        history
+            .as_ref()
+            .is_none_or(|history| frequently_used(cli, history, now, entry))
+    };
+    let filtered = |display: &str| {
+        cli.filter
+            .as_ref()
+            .is_none_or(|q| contains_ignoring_case(display, q))
+    };
+    let limit = cli.count.filter(|n| *n > 0).unwrap_or(usize::MAX);
+    if let Output::Json = cli.output {
+        let shown = entries
+            .iter()
+            .filter(|e| listed(e) && frequent(e) && filtered(&format.render(e)))
+            .take(limit);
+        print!("{}", json::to_json(shown));
+        return Ok(());
+    }
+
+    let stdin_lines: Vec<String> = match cli.stdin {
+        true => io::stdin().lines().collect::<io::Result<_>>()?,
+        false => Vec::new(),
+    };
+    let mut items = menu_items(entries, format);
+    if cli.with_comment {
+        for item in items.iter_mut().filter(|i| i.action.is_none()) {
+            if let Some(comment) = &item.entry.comment {
+                item.suffix = format!("{}{}", cli.separator, comment);
+            }
+        }
+    }
+    if cli.show_all {
+        for item in items.iter_mut().filter(|i| i.entry.hide()) {
+            item.display = format!("[hidden] {}", item.display);
+        }
+    }
+    let mut lines: Vec<&MenuItem> = match cli.stdin {
+        true => {
+            if cli.dmenu.is_none() {
+                let selection = stdin_lines.iter().map(|l| l.trim()).find(|l| !l.is_empty());
+                return launch(cli, &items, selection.unwrap_or_default());
+            }
+            stdin_lines
+                .iter()
+                .map(|l| l.trim())
+                .filter_map(|l| {
+                    let item = find_item(&items, l);
+                    if item.is_none() && !l.is_empty() {
+                        eprintln!("No entry matches '{}', leaving it out of the menu.", l);
+                    }
+                    item
+                })
+                .collect()
+        }
+        false => items.iter().filter(|i| listed(i.entry)).collect(),
+    };
+    if lines.is_empty() {
+        return Err(Error::NoEntries);
+    }
+    // Leaving every entry out with --filter or --min-score is a result, not a failure.
+    lines.retain(|i| (cli.stdin || frequent(i.entry)) && filtered(&i.display));
+    lines.truncate(limit);
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let icons = cli.resolve_icons.then(|| icons::lookup(cli));
+    let input = menu_input(cli, &lines, icons.as_ref());
+
+    if cli.dmenu.is_none() {
+        print!("{}", input.text);
+        Ok(())
+    } else {
+        run_command(cli, &items, &input)
+    }
+}
+
+/// Returns whether an entry is used enough for --min-score, or was never launched without
+/// --hide-new.
+fn frequently_used(cli: &Cli, history: &History, now: u64, entry: &DesktopEntry) -> bool {
+    let score = history.score(&entry.filename, now);
+    match score == 0.0 {
+        true => !cli.hide_new,
+        false => cli.min_score.is_none_or(|min| score >= min),
+    }
+}
+
+/// The text given to dmenu.
+#[derive(Default)]
+struct MenuInput<'a, 'b> {
+    text: String,
+    /// The --group-by-category headers among the lines.
+    headers: Vec<String>,
+    /// The items of the lines shortened by --max-line-length, by the line shown.
+    truncated: HashMap<String, &'b MenuItem<'a>>,
+}
+
+/// Joins the lines given to dmenu. Icons are given as files when they are looked up in `icons`.
+fn menu_input<'a, 'b>(
+    cli: &Cli,
+    lines: &[&'b MenuItem<'a>],
+    icons: Option<&IconLookup>,
+) -> MenuInput<'a, 'b> {
+    let separator = if cli.print0 { '\0' } else { '\n' };
+    let mut input = MenuInput::default();
+    let full: Vec<String> = lines.iter().map(|item| item.line()).collect();
+    let shown = match cli.line_limit() {
+        Some(limit) => truncate_lines(&full, limit),
+        None => full
+            .iter()
+            .map(|line| Cow::Borrowed(line.as_str()))
+            .collect(),
+    };
+    let mut category = None;
+    for (item, line) in lines.iter().zip(shown) {
+        let item_category = item.entry.primary_category();
+        if cli.group_by_category && category != Some(item_category) {
+            category = Some(item_category);
+            let header = format!("--- {} ---", item_category);
+            input.text.push_str(&header);
+            input.text.push(separator);
+            input.headers.push(header);
+        }
+        input.text.push_str(&line);
+        if let Cow::Owned(line) = line {
+            input.truncated.insert(line, item);
+        }
+        if let Some(icon) = item
+            .entry
+            .icon
+            .as_deref()
+            .filter(|_| cli.rofi_icons || icons.is_some())
+        {
+            // Rofi reads options after a NUL, separated from their values by a unit separator.
+            input.text.push_str("\0icon\x1f");
+            match icons.and_then(|icons| icons.find(icon)) {
+                Some(path) => input.text.push_str(&path.to_string_lossy()),
+                None => input.text.push_str(icon),
+            }
+        }
+        input.text.push(separator);
+    }
+    input
+}
+
+fn contains_ignoring_case(text: &str, query: &str) -> bool {
+    text.to_lowercase().contains(&query.to_lowercase())
+}
+
+/// Builds the dmenu command from the split --dmenu string followed by the --dmenu-arg arguments and
+/// the --prompt.
+fn dmenu_command(cli: &Cli) -> Result<Command, Error> {
+    let dmenu_split = cli.dmenu.as_deref().and_then(shlex::split);
+    let Some((program, args)) = dmenu_split.as_deref().and_then(<[String]>::split_first) else {
+        return Err(Error::InvalidDmenuCommand);
+    };
+    let mut command = Command::new(program);
+    command.args(args).args(&cli.dmenu_arg);
+    if let Some(prompt) = &cli.prompt {
+        command.arg(&cli.prompt_flag).arg(prompt);
+    }
+    Ok(command)
+}
+
+/// Runs dmenu and launches the selection, showing the menu again when a header is selected.
+fn run_command(cli: &Cli, items: &[MenuItem], input: &MenuInput) -> Result<(), Error> {
+    loop {
+        let mut command = dmenu_command(cli)?;
+        let mut menu_handle = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::spawning("dmenu command", command.get_program(), e, Error::Menu))?;
+        if let Some(stdin) = menu_handle.stdin.as_mut() {
+            // A menu may exit before reading every line, its selection is still used.
+            if let Err(e) = stdin.write_all(input.text.as_bytes()) {
+                eprintln!("Could not write the entries to the menu: {}", e);
+            }
+        }
+        let stdout = menu_handle.wait_with_output().map_err(Error::Menu)?.stdout;
+        let output = String::from_utf8_lossy(&stdout);
+
+        // With --print0 the menu may end the selection with either a NUL or a newline.
+        let selection = match cli.print0 {
+            true => output.split('\0').next().unwrap_or_default().trim(),
+            false => output.trim(),
+        };
+        if let Some(item) = input.truncated.get(selection) {
+            return launch_item(cli, item, &[]);
+        }
+        if !input.headers.iter().any(|h| h == selection) {
+            return launch(cli, items, selection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::test_support::named;
+
+    fn text(text: &str) -> MenuInput<'static, 'static> {
+        MenuInput {
+            text: text.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filter_ignores_case() {
+        assert!(contains_ignoring_case("GNOME Terminal", "term"));
+        assert!(contains_ignoring_case("Ünïcode", "ÜNÏ"));
+        assert!(!contains_ignoring_case("Firefox", "term"));
+        assert!(contains_ignoring_case("Firefox", ""));
+    }
+
+    #[test]
+    fn dmenu_args_are_appended_as_is() {
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--dmenu",
+            "rofi -dmenu -p 'Run:'",
+            "--dmenu-arg",
+            "-theme-str",
+            "--dmenu-arg",
+            "window { width: 50%; } 'quoted'",
+        ]);
+        let command = dmenu_command(&cli).unwrap();
+        assert_eq!(command.get_program(), "rofi");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "-dmenu",
+                "-p",
+                "Run:",
+                "-theme-str",
+                "window { width: 50%; } 'quoted'"
+            ]
+        );
+    }
+
+    #[test]
+    fn prompt_is_appended_after_its_flag() {
+        let args = |cli_args: &[&str]| {
+            let cli =
+                Cli::parse_from([&["dmenu-desktop", "--dmenu", "fuzzel -d"], cli_args].concat());
+            let command = dmenu_command(&cli).unwrap();
+            command.get_args().map(|a| a.to_owned()).collect::<Vec<_>>()
+        };
+        assert_eq!(args(&[]), ["-d"]);
+        assert_eq!(args(&["--prompt", "Apps: "]), ["-d", "-p", "Apps: "]);
+        assert_eq!(
+            args(&["--prompt", "-", "--prompt-flag", "--prompt"]),
+            ["-d", "--prompt", "-"]
+        );
+    }
+
+    #[test]
+    fn empty_dmenu_command_is_an_error() {
+        let cli = Cli::parse_from(["dmenu-desktop", "--dmenu", "  "]);
+        let error = run_command(&cli, &[], &MenuInput::default()).unwrap_err();
+        assert!(matches!(error, Error::InvalidDmenuCommand));
+    }
+
+    #[test]
+    fn env_info_counts_entries() {
+        let environment = Environment {
+            desktops: vec!["ubuntu".to_owned(), "GNOME".to_owned()],
+            ..Default::default()
+        };
+        let entries = [named("foot", "Foot", false), named("htop", "Htop", true)];
+        let info = env_info(&environment, &[PathBuf::from("/nonexistent")], &entries);
+        assert!(info.contains("Desktops: ubuntu:GNOME\n"));
+        assert!(info.contains("  /nonexistent (missing)\n"));
+        assert!(info.contains("Entries: 2 (1 hidden)\n"));
+    }
+
+    #[test]
+    fn startup_times_are_summed() {
+        let phases = [
+            ("Discovery", Duration::from_micros(250)),
+            ("Parsing", Duration::from_millis(12)),
+            ("Sorting", Duration::from_micros(750)),
+        ];
+        assert_eq!(
+            startup_times(&phases, 120),
+            "Discovery: 250.00µs\nParsing: 12.00ms\nSorting: 750.00µs\nTotal: 13.00ms\nEntries: 120\n"
+        );
+    }
+
+    #[test]
+    fn commands_are_deduplicated() {
+        let cli = Cli::parse_from(["dmenu-desktop"]);
+        let entries = [
+            named("zathura", "Zathura", false),
+            named("foot", "Foot", false),
+            named("foot", "Foot Server", false),
+            named("htop", "Htop", true),
+        ];
+        assert_eq!(
+            commands(&cli, &entries).into_iter().collect::<Vec<_>>(),
+            ["foot", "zathura"]
+        );
+        let cli = Cli::parse_from(["dmenu-desktop", "--show-all"]);
+        assert_eq!(commands(&cli, &entries).len(), 3);
+    }
+
+    #[test]
+    fn selected_entries() {
+        let entries = [
+            named("foot", "Foot", false),
+            named("foot-server", "Foot", false),
+            named("htop", "Htop", false),
+            named("old-htop", "Htop", true),
+        ];
+        let select_by = |field: &str, value: &str| {
+            let cli = Cli::parse_from(["dmenu-desktop", "--select", value, "--select-by", field]);
+            select(&cli, &entries, value).map(|e| e.filename.as_str())
+        };
+        assert_eq!(select_by("filename", "foot").unwrap(), "foot");
+        assert_eq!(select_by("filename", "old-htop").unwrap(), "old-htop");
+        assert_eq!(select_by("name", "Htop").unwrap(), "htop");
+        assert_eq!(
+            select_by("name", "Foot").unwrap_err().to_string(),
+            "Several entries match 'Foot': foot, foot-server."
+        );
+        assert!(matches!(
+            select_by("filename", "firefox"),
+            Err(Error::NoSuchEntry(_))
+        ));
+    }
+
+    #[test]
+    fn reversed_order() {
+        let mut entries = [
+            named("foot", "Foot", false),
+            named("zathura", "Zathura", false),
+            named("alacritty", "Alacritty", false),
+        ];
+        let names =
+            |entries: &[DesktopEntry]| entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+        sort_entries(&Cli::parse_from(["dmenu-desktop"]), &mut entries);
+        assert_eq!(names(&entries), ["Alacritty", "Foot", "Zathura"]);
+        sort_entries(
+            &Cli::parse_from(["dmenu-desktop", "--reverse"]),
+            &mut entries,
+        );
+        assert_eq!(names(&entries), ["Zathura", "Foot", "Alacritty"]);
+    }
+
+    #[test]
+    fn sort_fields() {
+        let at = |filename: &str, name: &str, exec: &str, secs: u64| DesktopEntry {
+            exec: exec.to_owned(),
+            mtime: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs)),
+            ..named(filename, name, false)
+        };
+        let mut entries = [
+            at("org.gnome.Nautilus", "Files", "nautilus", 30),
+            at("foot", "Foot", "foot", 10),
+            at("firefox", "Firefox", "firefox", 20),
+        ];
+        let mut sorted = |args: &[&str]| {
+            let cli = Cli::parse_from(["dmenu-desktop"].iter().chain(args));
+            sort_entries(&cli, &mut entries);
+            entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(&[]), ["Files", "Firefox", "Foot"]);
+        assert_eq!(
+            sorted(&["--sort-field", "exec"]),
+            ["Firefox", "Foot", "Files"]
+        );
+        assert_eq!(
+            sorted(&["--sort-field", "filename"]),
+            ["Firefox", "Foot", "Files"]
+        );
+        assert_eq!(
+            sorted(&["--sort-field", "mtime", "--reverse"]),
+            ["Files", "Firefox", "Foot"]
+        );
+    }
+
+    #[test]
+    fn natural_sort() {
+        let at = |filename: &str, name: &str, exec: &str| DesktopEntry {
+            exec: exec.to_owned(),
+            ..named(filename, name, false)
+        };
+        let mut entries = [
+            at("track-10", "Track 10", "player --track 1"),
+            at("track-2", "Track 2", "player --track 20"),
+            at("track-2-copy", "Track 2", "player --track 3"),
+        ];
+        let mut sorted = |args: &[&str]| {
+            let cli = Cli::parse_from(["dmenu-desktop"].iter().chain(args));
+            sort_entries(&cli, &mut entries);
+            entries
+                .iter()
+                .map(|e| e.filename.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(&[]), ["track-10", "track-2", "track-2-copy"]);
+        assert_eq!(
+            sorted(&["--sort", "natural"]),
+            ["track-2", "track-2-copy", "track-10"]
+        );
+        assert_eq!(
+            sorted(&["--sort", "natural", "--sort-field", "exec"]),
+            ["track-10", "track-2-copy", "track-2"]
+        );
+    }
+
+    #[test]
+    fn minimum_scores() {
+        let now = 10 * 24 * 3600;
+        let mut history = History::default();
+        for _ in 0..3 {
+            history.record("firefox", now);
+        }
+        history.record("foot", 0);
+        let used = |args: &[&str], filename: &str| {
+            let cli = Cli::parse_from(["dmenu-desktop"].iter().chain(args));
+            frequently_used(&cli, &history, now, &named(filename, filename, false))
+        };
+        assert!(used(&["--min-score", "5"], "firefox"));
+        assert!(!used(&["--min-score", "5"], "foot"));
+        assert!(used(&["--min-score", "5"], "htop"));
+        assert!(!used(&["--min-score", "5", "--hide-new"], "htop"));
+        assert!(used(&["--min-score", "0.5"], "foot"));
+        assert!(Cli::try_parse_from(["dmenu-desktop", "--hide-new"]).is_err());
+    }
+
+    #[test]
+    fn grouped_by_category() {
+        let categorized = |filename: &str, name: &str, categories: &[&str]| DesktopEntry {
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+            ..named(filename, name, false)
+        };
+        let mut entries = [
+            categorized("foot", "Foot", &["System", "TerminalEmulator"]),
+            categorized("firefox", "Firefox", &["Network", "WebBrowser"]),
+            categorized("notes", "Notes", &[]),
+            categorized("alacritty", "Alacritty", &["System"]),
+        ];
+        let cli = Cli::parse_from(["dmenu-desktop", "--group-by-category"]);
+        sort_entries(&cli, &mut entries);
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let lines: Vec<&MenuItem> = items.iter().collect();
+        let input = menu_input(&cli, &lines, None);
+        assert_eq!(
+            input.text,
+            "--- Network ---\nFirefox\n--- Other ---\nNotes\n--- System ---\nAlacritty\nFoot\n"
+        );
+        assert_eq!(
+            input.headers,
+            ["--- Network ---", "--- Other ---", "--- System ---"]
+        );
+
+        let input = menu_input(&Cli::parse_from(["dmenu-desktop"]), &lines, None);
+        assert_eq!(input.text, "Firefox\nNotes\nAlacritty\nFoot\n");
+        assert!(input.headers.is_empty());
+    }
+
+    #[test]
+    fn forced_visibility() {
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--force-show",
+            "installer",
+            "--force-hide",
+            "duplicate",
+            "--force-show",
+            "both",
+            "--force-hide",
+            "both",
+        ]);
+        let mut entries = [
+            named("installer", "Installer", true),
+            named("duplicate", "Duplicate", false),
+            named("both", "Both", false),
+            named("other", "Other", true),
+        ];
+        force_visibility(&cli, &mut entries);
+        let hidden: Vec<bool> = entries.iter().map(|e| e.hide()).collect();
+        assert_eq!(hidden, [false, true, true, true]);
+    }
+
+    #[test]
+    fn validate_is_a_subcommand() {
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--desktop",
+            "GNOME",
+            "validate",
+            "a.desktop",
+        ]);
+        assert!(
+            matches!(cli.command, Some(Commands::Validate { path }) if path == Path::new("a.desktop"))
+        );
+        assert!(cli.args.is_empty());
+        assert!(Cli::try_parse_from(["dmenu-desktop", "validate"]).is_err());
+        assert!(Cli::try_parse_from(["dmenu-desktop", "--validate", "a.desktop"]).is_err());
+    }
+
+    #[test]
+    fn client_takes_no_other_options() {
+        assert!(Cli::parse_from(["dmenu-desktop", "--client"]).client);
+        for other in [&["--dmenu", "rofi -dmenu"][..], &["--stdin"], &["file.txt"]] {
+            let argv = ["dmenu-desktop", "--client"].iter().chain(other);
+            assert!(Cli::try_parse_from(argv).is_err());
+        }
+    }
+
+    #[test]
+    fn missing_dmenu_is_not_found() {
+        let cli = Cli::parse_from(["dmenu-desktop", "--dmenu", "dmenu-desktop-missing-menu -i"]);
+        let error = run_command(&cli, &[], &MenuInput::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "dmenu command 'dmenu-desktop-missing-menu' not found in PATH."
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_selection_is_an_error() {
+        let cli = Cli::parse_from(["dmenu-desktop", "--dmenu", r"printf '\377Foot'"]);
+        let entries = [named("foot", "Foot", false)];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let error = run_command(&cli, &items, &text("Foot\n")).unwrap_err();
+        assert!(matches!(error, Error::NoMatch(selection) if selection == "\u{fffd}Foot"));
+    }
+
+    #[test]
+    fn selecting_a_header_shows_the_menu_again() {
+        let marker = env::temp_dir().join(format!("dmenu-desktop-header-{}", std::process::id()));
+        let script = format!(
+            "sh -c 'if [ -e {0} ]; then echo Missing; else touch {0}; echo \"--- System ---\"; fi'",
+            marker.display()
+        );
+        let cli = Cli::parse_from(["dmenu-desktop", "--dmenu", &script]);
+        let headers = ["--- System ---".to_owned()];
+        let error = run_command(
+            &cli,
+            &[],
+            &MenuInput {
+                headers: headers.to_vec(),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::NoMatch(selection) if selection == "Missing"));
+        std::fs::remove_file(marker).unwrap();
+    }
+
+    #[test]
+    fn show_menu_exit_codes() {
+        let entries = [named("foot", "Foot", false)];
+        let exit_code = |args: &[&str], entries: &[DesktopEntry]| {
+            let cli = Cli::parse_from(["dmenu-desktop"].iter().chain(args));
+            let format = Format::from(&EntryType::Name);
+            show_menu(&cli, &format, entries).map_or_else(|e| e.exit_code(), |()| 0)
+        };
+        assert_eq!(
+            exit_code(&["--dmenu", "echo Foot", "--dry-run"], &entries),
+            0
+        );
+        assert_eq!(exit_code(&["--dmenu", "echo Htop"], &entries), 1);
+        assert_eq!(exit_code(&["--dmenu", "true"], &entries), 2);
+        assert_eq!(exit_code(&["--dmenu", "echo Foot"], &[]), 3);
+        assert_eq!(exit_code(&["--filter", "htop"], &entries), 0);
+        assert_eq!(
+            exit_code(&["--dmenu", "false", "--filter", "htop"], &entries),
+            0
+        );
+        assert_eq!(exit_code(&["--filter", "foot"], &[]), 3);
+        let hidden = [named("htop", "Htop", true)];
+        assert_eq!(exit_code(&["--filter", "htop"], &hidden), 3);
+    }
+
+    #[test]
+    fn truncated_lines_launch_their_item() {
+        let entries = [
+            named("code", "Visual Studio Code", false),
+            named("codium", "Visual Studio Codium", false),
+            named("alacritty", "Alacritty", false),
+        ];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let lines: Vec<&MenuItem> = items.iter().collect();
+        let cli = Cli::parse_from([
+            "dmenu-desktop",
+            "--dmenu",
+            "tail -n 1",
+            "--max-line-length",
+            "5",
+            "--dry-run",
+        ]);
+        let input = menu_input(&cli, &lines, None);
+        assert_eq!(
+            input.text,
+            "Visual Studio Code\nVisual Studio Codium\nAlac…\n"
+        );
+        assert_eq!(input.truncated["Alac…"].entry.filename, "alacritty");
+        assert!(run_command(&cli, &items, &input).is_ok());
+    }
+
+    #[test]
+    fn menu_exiting_early_is_not_an_error() {
+        let cli = Cli::parse_from(["dmenu-desktop", "--dmenu", "true"]);
+        let error = run_command(&cli, &[], &text(&"Foot\n".repeat(100_000))).unwrap_err();
+        assert!(matches!(error, Error::Cancelled));
+    }
+
+    #[test]
+    fn empty_selection_is_cancelled() {
+        let cli = Cli::parse_from(["dmenu-desktop", "--dmenu", "true", "--exec-unmatched"]);
+        let entries = [named("foot", "Foot", false)];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let error = run_command(&cli, &items, &text("Foot\n")).unwrap_err();
+        assert!(matches!(error, Error::Cancelled));
+    }
+}
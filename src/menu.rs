@@ -0,0 +1,453 @@
+//! The lines of the menu and the lookup of the selected line.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::collate;
+use crate::entry::{DesktopAction, DesktopEntry};
+use crate::format::Format;
+
+/// A selectable line of the menu, either an entry or one of its actions.
+pub(crate) struct MenuItem<'a> {
+    pub(crate) display: String,
+    /// Shown after the display, such as the comment with --with-comment, but not needed to match.
+    pub(crate) suffix: String,
+    pub(crate) entry: &'a DesktopEntry,
+    pub(crate) action: Option<&'a DesktopAction>,
+}
+
+impl MenuItem<'_> {
+    /// The line shown in the menu.
+    pub(crate) fn line(&self) -> String {
+        format!("{}{}", self.display, self.suffix)
+    }
+
+    pub(crate) fn exec(&self) -> &str {
+        match self.action {
+            Some(action) => &action.exec,
+            None => &self.entry.exec,
+        }
+    }
+}
+
+/// Returns the menu items of every entry, each entry followed by its actions.
+pub(crate) fn menu_items<'a>(entries: &'a [DesktopEntry], format: &Format) -> Vec<MenuItem<'a>> {
+    let mut items = Vec::new();
+    for entry in entries {
+        // Selections are trimmed, so lines are too for them to match.
+        let field = format.render(entry).trim().to_owned();
+        items.push(MenuItem {
+            display: field.clone(),
+            suffix: String::new(),
+            entry,
+            action: None,
+        });
+        items.extend(entry.actions.iter().map(|action| MenuItem {
+            display: format!("{}: {}", field, action.name),
+            suffix: String::new(),
+            entry,
+            action: Some(action),
+        }));
+    }
+    disambiguate(&mut items);
+    items
+}
+
+/// Appends the desktop filename to shown lines that would otherwise be identical.
+fn disambiguate(items: &mut [MenuItem]) {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for item in items.iter().filter(|i| !i.entry.hide()) {
+        *counts.entry(item.display.clone()).or_default() += 1;
+    }
+    for item in items.iter_mut().filter(|i| !i.entry.hide()) {
+        if counts[&item.display] > 1 {
+            item.display = format!("{} ({})", item.display, item.entry.filename);
+        }
+    }
+}
+
+/// Finds the entry whose GenericName or one of whose Keywords matches the selection ignoring case,
+/// preferring shown entries over hidden ones.
+pub(crate) fn find_by_keyword<'a, 'b>(
+    items: &'b [MenuItem<'a>],
+    selection: &str,
+) -> Option<&'b MenuItem<'a>> {
+    let selection = selection.to_lowercase();
+    let matches = || {
+        items.iter().filter(|i| i.action.is_none()).filter(|i| {
+            let entry = i.entry;
+            entry
+                .generic_name
+                .iter()
+                .chain(&entry.keywords)
+                .any(|k| k.to_lowercase() == selection)
+        })
+    };
+    matches()
+        .find(|i| !i.entry.hide())
+        .or_else(|| matches().next())
+}
+
+/// Finds the item whose entry rendered with `format`, followed by the action name for actions, is
+/// the selection, preferring shown items over hidden ones.
+pub(crate) fn find_by_format<'a, 'b>(
+    items: &'b [MenuItem<'a>],
+    selection: &str,
+    format: &Format,
+) -> Option<&'b MenuItem<'a>> {
+    let key = |item: &MenuItem| {
+        let field = format.render(item.entry);
+        let field = field.trim();
+        match item.action {
+            Some(action) => format!("{}: {}", field, action.name),
+            None => field.to_owned(),
+        }
+    };
+    let matches = || items.iter().filter(|i| key(i) == selection);
+    matches()
+        .find(|i| !i.entry.hide())
+        .or_else(|| matches().next())
+}
+
+/// Finds the single shown item whose line starts with the selection ignoring case, taking an entry
+/// over its actions when they all match as well.
+pub(crate) fn find_by_prefix<'a, 'b>(
+    items: &'b [MenuItem<'a>],
+    selection: &str,
+) -> Option<&'b MenuItem<'a>> {
+    let prefix = selection.to_lowercase();
+    let mut matches = items
+        .iter()
+        .filter(|i| !i.entry.hide() && i.display.to_lowercase().starts_with(&prefix));
+    let first = matches.next()?;
+    let unique = match first.action {
+        None => matches.all(|i| std::ptr::eq(i.entry, first.entry)),
+        Some(_) => matches.next().is_none(),
+    };
+    unique.then_some(first)
+}
+
+/// Finds the item whose line starts the selection followed by whitespace, returning it with the rest
+/// of the selection. The longest line is taken, so that an action wins over its entry.
+pub(crate) fn find_with_arguments<'a, 'b, 'c>(
+    items: &'b [MenuItem<'a>],
+    selection: &'c str,
+) -> Option<(&'b MenuItem<'a>, &'c str)> {
+    items
+        .iter()
+        .filter_map(|item| {
+            let rest = selection.strip_prefix(&item.display)?;
+            rest.starts_with(char::is_whitespace)
+                .then_some((item, rest))
+        })
+        .min_by_key(|(item, rest)| (rest.len(), item.entry.hide()))
+}
+
+/// Finds the item for a selected line ignoring case, with or without its suffix, when a single
+/// shown item or else a single hidden one matches.
+pub(crate) fn find_item_ignoring_case<'a, 'b>(
+    items: &'b [MenuItem<'a>],
+    selection: &str,
+) -> Option<&'b MenuItem<'a>> {
+    let selection = collate::fold_case(selection);
+    let matches: Vec<&MenuItem> = items
+        .iter()
+        .filter(|i| {
+            collate::fold_case(&i.display) == selection
+                || (!i.suffix.is_empty() && collate::fold_case(&i.line()) == selection)
+        })
+        .collect();
+    let shown: Vec<&MenuItem> = matches
+        .iter()
+        .copied()
+        .filter(|i| !i.entry.hide())
+        .collect();
+    match (&shown[..], &matches[..]) {
+        ([item], _) | ([], [item]) => Some(*item),
+        _ => None,
+    }
+}
+
+/// Finds the item for a selected line, with or without its suffix, preferring shown items over
+/// hidden ones.
+pub(crate) fn find_item<'a, 'b>(
+    items: &'b [MenuItem<'a>],
+    selection: &str,
+) -> Option<&'b MenuItem<'a>> {
+    let matches = || {
+        items.iter().filter(|i| {
+            i.display == selection
+                || (!i.suffix.is_empty()
+                    && selection.strip_prefix(&i.display) == Some(i.suffix.as_str()))
+        })
+    };
+    matches()
+        .find(|i| !i.entry.hide())
+        .or_else(|| matches().next())
+}
+
+/// Truncates a line to `limit` grapheme clusters, the last of which is an ellipsis, so that
+/// combining marks, emoji sequences and flags are neither split nor counted apart.
+pub(crate) fn truncate(line: &str, limit: usize) -> Cow<'_, str> {
+    let starts: Vec<usize> = line.grapheme_indices(true).map(|(i, _)| i).collect();
+    match starts.get(limit) {
+        Some(_) => Cow::Owned(format!("{}…", &line[..starts[limit - 1]])),
+        None => Cow::Borrowed(line),
+    }
+}
+
+/// Truncates the lines of the menu with --max-line-length. Lines that would be truncated alike are
+/// left whole, so that every truncated line stands for a single item.
+pub(crate) fn truncate_lines(lines: &[String], limit: usize) -> Vec<Cow<'_, str>> {
+    let truncated: Vec<Cow<str>> = lines.iter().map(|line| truncate(line, limit)).collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for line in &truncated {
+        *counts.entry(line).or_default() += 1;
+    }
+    lines
+        .iter()
+        .zip(&truncated)
+        .map(|(line, truncated)| match counts[&**truncated] {
+            1 => truncated.clone(),
+            _ => Cow::Borrowed(line.as_str()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{locale_variants, Environment};
+    use crate::test_support::{named, parse};
+    use crate::EntryType;
+
+    fn displays(items: &[MenuItem]) -> Vec<String> {
+        items.iter().map(|i| i.display.clone()).collect()
+    }
+
+    #[test]
+    fn colliding_names_are_disambiguated() {
+        let entries = [
+            named("firefox", "Firefox", false),
+            named("org.mozilla.firefox", "Firefox", false),
+            named("foot", "Foot", false),
+            named("hidden-foot", "Foot", true),
+        ];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        assert_eq!(
+            displays(&items),
+            [
+                "Firefox (firefox)",
+                "Firefox (org.mozilla.firefox)",
+                "Foot",
+                "Foot"
+            ]
+        );
+        let selected = find_item(&items, "Firefox (org.mozilla.firefox)").unwrap();
+        assert_eq!(selected.entry.filename, "org.mozilla.firefox");
+        assert!(find_item(&items, "Firefox").is_none());
+    }
+
+    #[test]
+    fn keyword_matches() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox\n\
+            GenericName=Web Browser\nKeywords=Internet;WWW;\nKeywords[de]=Netz;\n";
+        let environment = Environment {
+            locales: locale_variants("de_DE"),
+            ..Default::default()
+        };
+        let entries = [
+            named("foot", "Foot", false),
+            parse(contents, &environment).unwrap(),
+        ];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let found = |s| find_by_keyword(&items, s).map(|i| i.entry.name.as_str());
+        assert_eq!(found("web browser"), Some("Firefox"));
+        assert_eq!(found("NETZ"), Some("Firefox"));
+        assert_eq!(found("www"), None);
+        assert_eq!(found("Foot"), None);
+    }
+
+    #[test]
+    fn padded_fields_match() {
+        let contents = "[Desktop Entry]\nType=Application\nName= Firefox \t\nExec=firefox\n";
+        let entries = [parse(contents, &Environment::default()).unwrap()];
+        for template in ["{name}", " {name} {comment}"] {
+            let items = menu_items(&entries, &Format::parse(template).unwrap());
+            assert_eq!(find_item(&items, "Firefox").unwrap().exec(), "firefox");
+        }
+    }
+
+    #[test]
+    fn field_matches() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox %u\n\
+            Actions=private;\n\
+            [Desktop Action private]\nName=Private\nExec=firefox --private-window %u\n";
+        let entries = [
+            named("foot", "Foot", false),
+            parse(contents, &Environment::default()).unwrap(),
+        ];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let command = Format::from(&EntryType::Command);
+        let found = |s| find_by_format(&items, s, &command).map(|i| i.exec());
+        assert_eq!(found("firefox"), Some("firefox %u"));
+        assert_eq!(
+            found("firefox: Private"),
+            Some("firefox --private-window %u")
+        );
+        assert_eq!(found("foot"), Some("foot"));
+        assert_eq!(found("Firefox"), None);
+    }
+
+    #[test]
+    fn suffixes_are_optional() {
+        let entries = [named("foot", "Foot", false), named("htop", "Htop", false)];
+        let mut items = menu_items(&entries, &Format::from(&EntryType::Name));
+        items[0].suffix = "\tTerminal emulator".to_owned();
+        assert_eq!(items[0].line(), "Foot\tTerminal emulator");
+        let found = |s| find_item(&items, s).map(|i| i.entry.filename.as_str());
+        assert_eq!(found("Foot\tTerminal emulator"), Some("foot"));
+        assert_eq!(found("Foot"), Some("foot"));
+        assert_eq!(found("Foot\tOther"), None);
+        assert_eq!(found("Htop"), Some("htop"));
+    }
+
+    #[test]
+    fn prefix_matches() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox %u\n\
+            Actions=private;\n\
+            [Desktop Action private]\nName=Private\nExec=firefox --private-window %u\n";
+        let entries = [
+            parse(contents, &Environment::default()).unwrap(),
+            named("foot", "Foot", false),
+            named("files", "Files", false),
+            named("htop", "Htop", true),
+        ];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let found = |s| find_by_prefix(&items, s).map(|i| i.exec());
+        assert_eq!(found("fir"), Some("firefox %u"));
+        assert_eq!(found("FIREFOX: p"), Some("firefox --private-window %u"));
+        assert_eq!(found("fo"), Some("foot"));
+        assert_eq!(found("f"), None);
+        assert_eq!(found("fi"), None);
+        assert_eq!(found("htop"), None);
+        assert_eq!(found("zathura"), None);
+    }
+
+    #[test]
+    fn appended_arguments() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox %u\n\
+            Actions=private;\n\
+            [Desktop Action private]\nName=Private\nExec=firefox --private-window %u\n";
+        let entries = [
+            parse(contents, &Environment::default()).unwrap(),
+            named("hidden-foot", "Foot", true),
+            named("foot", "Foot", false),
+        ];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let found = |s| {
+            find_with_arguments(&items, s)
+                .map(|(i, rest)| (i.entry.filename.as_str(), i.exec(), rest))
+        };
+        assert_eq!(
+            found("Firefox --new-tab x"),
+            Some(("app", "firefox %u", " --new-tab x"))
+        );
+        assert_eq!(
+            found("Firefox: Private\t-P"),
+            Some(("app", "firefox --private-window %u", "\t-P"))
+        );
+        assert_eq!(found("Foot -e htop"), Some(("foot", "foot", " -e htop")));
+        assert_eq!(found("Firefox"), None);
+        assert_eq!(found("Firefoxes"), None);
+    }
+
+    #[test]
+    fn case_insensitive_matches() {
+        let entries = [
+            named("foot", "Foot", false),
+            named("hidden-htop", "Htop", true),
+            named("gimp", "GIMP", false),
+            named("gimp-2", "Gimp", false),
+            named("strasse", "Straße", false),
+        ];
+        let mut items = menu_items(&entries, &Format::from(&EntryType::Name));
+        items[0].suffix = "\tTerminal".to_owned();
+        let found = |s| find_item_ignoring_case(&items, s).map(|i| i.entry.filename.as_str());
+        assert_eq!(found("foot"), Some("foot"));
+        assert_eq!(found("FOOT\tterminal"), Some("foot"));
+        assert_eq!(found("htop"), Some("hidden-htop"));
+        assert_eq!(found("STRASSE"), Some("strasse"));
+        assert_eq!(found("gimp"), None);
+        assert_eq!(found("fo"), None);
+    }
+
+    #[test]
+    fn truncated_lines() {
+        assert_eq!(truncate("Firefox", 7), "Firefox");
+        assert_eq!(truncate("Firefox", 4), "Fir…");
+        assert_eq!(truncate("Cafe\u{301} Cre\u{300}me", 5), "Cafe\u{301}…");
+        assert_eq!(truncate("Ünïcödé", 3), "Ün…");
+        assert_eq!(truncate("🇩🇪🇫🇷🇮🇹", 3), "🇩🇪🇫🇷🇮🇹");
+        assert_eq!(truncate("🇩🇪🇫🇷🇮🇹 Flags", 3), "🇩🇪🇫🇷…");
+        assert_eq!(truncate("👩\u{200D}💻👍🏽 Apps", 3), "👩\u{200D}💻👍🏽…");
+        assert_eq!(
+            truncate("\u{1100}\u{1161}\u{11A8}ab", 2),
+            "\u{1100}\u{1161}\u{11A8}…"
+        );
+    }
+
+    #[test]
+    fn lines_truncated_alike_are_left_whole() {
+        let lines = [
+            "Visual Studio Code",
+            "Visual Studio Codium",
+            "LibreOffice Writer",
+            "Vi",
+        ]
+        .map(String::from);
+        assert_eq!(
+            truncate_lines(&lines, 10),
+            [
+                "Visual Studio Code",
+                "Visual Studio Codium",
+                "LibreOffi…",
+                "Vi"
+            ]
+        );
+    }
+
+    #[test]
+    fn shown_items_are_preferred() {
+        let entries = [
+            named("hidden-foot", "Foot", true),
+            named("foot", "Foot", false),
+        ];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        assert_eq!(find_item(&items, "Foot").unwrap().entry.filename, "foot");
+    }
+
+    #[test]
+    fn actions() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox %u\n\
+            Actions=new-window;private;missing;\n\
+            [Desktop Action new-window]\nName=New Window\nExec=firefox --new-window %u\n\
+            [Desktop Action private]\nName=New Private Window\nExec=firefox --private-window %u\n";
+        let entries = [parse(contents, &Environment::default()).unwrap()];
+        let items = menu_items(&entries, &Format::from(&EntryType::Name));
+        let lines: Vec<_> = items
+            .iter()
+            .map(|i| (i.display.as_str(), i.exec()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("Firefox", "firefox %u"),
+                ("Firefox: New Window", "firefox --new-window %u"),
+                ("Firefox: New Private Window", "firefox --private-window %u"),
+            ]
+        );
+    }
+}
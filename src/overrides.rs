@@ -0,0 +1,183 @@
+//! Replacement Exec and Name keys of entries, read from `$XDG_CONFIG_HOME/dmenu-desktop/overrides`.
+//!
+//! Each key outside of a group, such as `spotify = spotify --no-zygote`, replaces the Exec key of
+//! the entry with that desktop file ID. A group named by the ID may set `Exec` and `Name` instead:
+//!
+//! ```ini
+//! [org.gnome.Nautilus]
+//! Name=Nautilus
+//! Exec=nautilus --new-window %U
+//! ```
+//!
+//! IDs may end with `.desktop`, and values are escaped like those of desktop files. An empty Exec
+//! override is ignored with a warning, leaving the entry's own Exec key.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::discovery::load_source;
+use crate::entry::{unescape, DesktopEntry, Skip};
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Override {
+    exec: Option<String>,
+    name: Option<String>,
+}
+
+/// Overrides by desktop file ID.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Overrides(BTreeMap<String, Override>);
+
+impl Overrides {
+    /// Reads the overrides at `path`, or at the default location, where a missing file overrides
+    /// nothing.
+    pub(crate) fn load(path: Option<&Path>) -> Overrides {
+        let default = || config::dir().map(|d| d.join("overrides"));
+        let Some(path) = path.map(PathBuf::from).or_else(default) else {
+            return Overrides::default();
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Overrides::default(),
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", path.display(), e);
+                return Overrides::default();
+            }
+        };
+        Overrides::parse(&contents).unwrap_or_else(|e| {
+            eprintln!("Ignoring {}: {}", path.display(), e);
+            Overrides::default()
+        })
+    }
+
+    fn parse(contents: &str) -> Result<Overrides, ini::ParseError> {
+        let ini = load_source(contents)?;
+        let mut overrides = BTreeMap::new();
+        let id = |key: &str| key.strip_suffix(".desktop").unwrap_or(key).to_owned();
+        // An empty Exec key would only fail once launched, so it is left out as from_ini does.
+        let exec = |id: &str, exec: &str| {
+            let exec = unescape(exec);
+            if exec.trim().is_empty() {
+                eprintln!(
+                    "Ignoring the Exec override of {}: {}",
+                    id,
+                    Skip::Empty("Exec")
+                );
+                return None;
+            }
+            Some(exec)
+        };
+        for (section, properties) in ini.iter() {
+            match section {
+                None => {
+                    for (key, value) in properties.iter() {
+                        let id = id(key);
+                        if let Some(exec) = exec(&id, value) {
+                            let entry: &mut Override = overrides.entry(id).or_default();
+                            entry.exec = Some(exec);
+                        }
+                    }
+                }
+                Some(section) => {
+                    let id = id(section);
+                    let entry = overrides.entry(id.clone()).or_default();
+                    if let Some(value) = properties.get("Exec") {
+                        entry.exec = exec(&id, value);
+                    }
+                    if let Some(name) = properties.get("Name") {
+                        entry.name = Some(unescape(name).trim().to_owned());
+                    }
+                }
+            }
+        }
+        Ok(Overrides(overrides))
+    }
+
+    /// Replaces the keys of the entries with an override, warning with `verbose` about the
+    /// overrides of missing entries.
+    pub(crate) fn apply(&self, entries: &mut [DesktopEntry], verbose: bool) {
+        for (id, with) in &self.0 {
+            let Some(entry) = entries.iter_mut().find(|e| &e.filename == id) else {
+                if verbose {
+                    eprintln!(
+                        "No entry has the desktop file ID {}, ignoring its override",
+                        id
+                    );
+                }
+                continue;
+            };
+            if let Some(exec) = &with.exec {
+                entry.exec = exec.clone();
+                // DBus activation would start the application without the new Exec key.
+                entry.dbus_activatable = false;
+            }
+            if let Some(name) = &with.name {
+                entry.name = name.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::named;
+
+    fn activatable(filename: &str, name: &str) -> DesktopEntry {
+        DesktopEntry {
+            dbus_activatable: true,
+            ..named(filename, name, false)
+        }
+    }
+
+    #[test]
+    fn exec_and_name_are_replaced() {
+        let overrides = Overrides::parse(
+            "spotify = spotify --no-zygote\n\
+             [org.gnome.Nautilus.desktop]\nName=Nautilus\nExec=nautilus\\s--new-window %U\n\
+             [missing]\nName=Missing\n",
+        )
+        .unwrap();
+        let mut entries = [
+            activatable("spotify", "Spotify"),
+            activatable("org.gnome.Nautilus", "Files"),
+            activatable("foot", "Foot"),
+        ];
+        overrides.apply(&mut entries, false);
+        assert_eq!(entries[0].exec, "spotify --no-zygote");
+        assert_eq!(entries[0].name, "Spotify");
+        assert!(!entries[0].dbus_activatable);
+        assert_eq!(entries[1].exec, "nautilus --new-window %U");
+        assert_eq!(entries[1].name, "Nautilus");
+        assert_eq!(entries[2].exec, "foot");
+        assert!(entries[2].dbus_activatable);
+    }
+
+    #[test]
+    fn empty_exec_is_ignored() {
+        let overrides = Overrides::parse(
+            "spotify = \nfoot = \\s\n[org.gnome.Nautilus]\nName=Nautilus\nExec=\n",
+        )
+        .unwrap();
+        let mut entries = [
+            activatable("spotify", "Spotify"),
+            activatable("foot", "Foot"),
+            activatable("org.gnome.Nautilus", "Files"),
+        ];
+        overrides.apply(&mut entries, false);
+        assert_eq!(entries[0].exec, "spotify");
+        assert_eq!(entries[1].exec, "foot");
+        assert!(entries[1].dbus_activatable);
+        assert_eq!(entries[2].exec, "org.gnome.Nautilus");
+        assert_eq!(entries[2].name, "Nautilus");
+    }
+
+    #[test]
+    fn missing_file_overrides_nothing() {
+        let path = Path::new("/nonexistent/overrides");
+        assert_eq!(Overrides::load(Some(path)), Overrides::default());
+    }
+}
@@ -0,0 +1,89 @@
+//! The terminal emulator entries with Terminal=true run in, found when --terminal is not given.
+
+use std::env;
+
+use crate::exec::find_in_path;
+
+/// Terminal emulators probed when no terminal is given, with the template used to run a
+/// command in each.
+const TERMINALS: &[(&str, &str)] = &[
+    ("foot", "{} {}"),
+    ("alacritty", "{} -e {}"),
+    ("kitty", "{} {}"),
+    ("wezterm", "{} start -- {}"),
+    ("gnome-terminal", "{} -- {}"),
+    ("konsole", "{} -e {}"),
+    ("xfce4-terminal", "{} -x {}"),
+    ("urxvt", "{} -e {}"),
+    ("st", "{} -e {}"),
+    ("xterm", "{} -e {}"),
+];
+
+/// Returns a terminal template for the first installed terminal, trying `$TERMINAL` first and
+/// then `order`, or the built-in list when `order` is empty.
+pub(crate) fn detect(order: &[String]) -> Option<String> {
+    let candidates: Vec<String> = match order.is_empty() {
+        true => TERMINALS.iter().map(|(name, _)| name.to_string()).collect(),
+        false => order.to_vec(),
+    };
+    env::var("TERMINAL")
+        .ok()
+        .filter(|t| !t.is_empty())
+        .into_iter()
+        .chain(candidates)
+        .find(|program| find_in_path(program).is_some())
+        .map(|program| template(&program))
+}
+
+/// Returns the template for a terminal command, which is the command followed by `exec_arg`
+/// unless it already is a template containing `{}`.
+pub(crate) fn with_exec_arg(terminal: &str, exec_arg: &str) -> String {
+    if terminal.contains("{}") {
+        return terminal.to_owned();
+    }
+    match exec_arg.is_empty() {
+        true => format!("{} {{}}", terminal),
+        false => {
+            let quoted = shlex::try_quote(exec_arg).unwrap_or_default();
+            format!("{} {} {{}}", terminal, quoted)
+        }
+    }
+}
+
+/// Returns the template for a terminal program, using `-e` for unknown terminals.
+fn template(program: &str) -> String {
+    let name = program.rsplit('/').next().unwrap_or(program);
+    let style = TERMINALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map_or("{} -e {}", |(_, style)| style);
+    let quoted = shlex::try_quote(program).unwrap_or_default();
+    style.replacen("{}", &quoted, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        assert_eq!(template("foot"), "foot {}");
+        assert_eq!(template("/usr/bin/alacritty"), "/usr/bin/alacritty -e {}");
+        assert_eq!(template("wezterm"), "wezterm start -- {}");
+        assert_eq!(template("my term"), "'my term' -e {}");
+    }
+
+    #[test]
+    fn exec_args() {
+        assert_eq!(with_exec_arg("xterm", "-e"), "xterm -e {}");
+        assert_eq!(
+            with_exec_arg("foot -T 'My Term'", ""),
+            "foot -T 'My Term' {}"
+        );
+        assert_eq!(
+            with_exec_arg("gnome-terminal", "--"),
+            "gnome-terminal -- {}"
+        );
+        assert_eq!(with_exec_arg("st -e {}", "-x"), "st -e {}");
+    }
+}
@@ -0,0 +1,54 @@
+//! Fixtures shared by the tests of the modules.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::discovery::load_source;
+use crate::entry::{DesktopEntry, Environment, Skip};
+
+/// Parses the source of a desktop file the way reading the application directories does, as
+/// `/apps/app.desktop`.
+pub(crate) fn parse(contents: &str, environment: &Environment) -> Result<DesktopEntry, Skip> {
+    parse_as("app", contents, environment)
+}
+
+/// Parses the source of a desktop file as the one with the given desktop file ID in `/apps`.
+pub(crate) fn parse_as(
+    filename: &str,
+    contents: &str,
+    environment: &Environment,
+) -> Result<DesktopEntry, Skip> {
+    let ini = load_source(contents).map_err(Skip::Ini)?;
+    let location = Path::new("/apps").join(format!("{filename}.desktop"));
+    DesktopEntry::from_ini(filename, &location, ini, environment)
+}
+
+/// Returns an application running its desktop file ID, hidden with NoDisplay when `hidden`.
+pub(crate) fn named(filename: &str, name: &str, hidden: bool) -> DesktopEntry {
+    let contents = format!(
+        "[Desktop Entry]\nType=Application\nName={name}\nExec={filename}\nNoDisplay={hidden}\n"
+    );
+    parse_as(filename, &contents, &Environment::default()).unwrap()
+}
+
+/// Creates an empty directory of its own for a test. Tests run in parallel within one process, so
+/// every directory gets a number besides the name, which only makes it recognizable.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let number = NEXT.fetch_add(1, Ordering::Relaxed);
+    let dir = env::temp_dir().join(format!(
+        "dmenu-desktop-{}-{number}-{name}",
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a file, creating the directories it is in.
+pub(crate) fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
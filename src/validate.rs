@@ -0,0 +1,202 @@
+//! Checking a single desktop file for the validate subcommand. Every reason the file would be
+//! skipped or hidden is reported, rather than the first one as when entries are read, along with
+//! the problems that only show when it is launched.
+
+use std::fs;
+use std::path::Path;
+
+use ini::Properties;
+
+use crate::discovery::load_source;
+use crate::entry::{parse_list, unescape, Environment, HideReason, Skip};
+use crate::error::Error;
+use crate::exec::{malformed_field_codes, parse_exec};
+
+/// Prints the problems of a desktop file, failing when it would not be listed.
+pub(crate) fn run(path: &Path, environment: &Environment) -> Result<(), Error> {
+    let source = fs::read_to_string(path)?;
+    let report = check(&source, environment);
+    for problem in &report.problems {
+        println!("{}: {}", path.display(), problem);
+    }
+    match report.listed {
+        true => Ok(()),
+        false => Err(Error::Invalid(path.to_owned())),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    problems: Vec<String>,
+    /// Whether the entry would be listed in the menu despite the problems.
+    listed: bool,
+}
+
+impl Report {
+    fn skip(&mut self, problem: impl ToString) {
+        self.problems.push(problem.to_string());
+        self.listed = false;
+    }
+}
+
+fn check(source: &str, environment: &Environment) -> Report {
+    let mut report = Report {
+        listed: true,
+        ..Default::default()
+    };
+    let ini = match load_source(source) {
+        Ok(ini) => ini,
+        Err(e) => {
+            report.skip(Skip::Ini(e));
+            return report;
+        }
+    };
+    let Some(section) = ini.section(Some("Desktop Entry")) else {
+        report.skip(Skip::NoSection);
+        return report;
+    };
+
+    let is_link = section.get("Type") == Some("Link");
+    match section.get("Type") {
+        Some("Application") => {}
+        Some("Link") if environment.include_links => {}
+        other => report.skip(Skip::Type(other.map(str::to_owned))),
+    }
+    if environment.localized(section, "Name").is_none() {
+        report.skip(Skip::Missing("Name"));
+    }
+    let key = if is_link { "URL" } else { "Exec" };
+    match section.get(key) {
+        None => report.skip(Skip::Missing(key)),
+        Some(value) if value.trim().is_empty() => report.skip(Skip::Empty(key)),
+        Some(_) if is_link => {}
+        Some(exec) => check_exec("Exec", &unescape(exec), &mut report.problems),
+    }
+
+    if section.get("Hidden") == Some("true") {
+        report.skip(HideReason::Hidden);
+    }
+    if section.get("NoDisplay") == Some("true") {
+        report.skip(HideReason::NoDisplay);
+    }
+    if !environment.shows(section.get("OnlyShowIn"), section.get("NotShowIn")) {
+        report.skip(HideReason::NotShownIn);
+    }
+    let path = section.get("Path").map(Path::new);
+    if let Some(try_exec) = section
+        .get("TryExec")
+        .filter(|t| !environment.finds(t, path))
+    {
+        report.skip(HideReason::TryExec(try_exec.to_owned()));
+    }
+
+    // Broken actions are left out of the menu, without hiding the entry.
+    for id in parse_list(section.get("Actions").unwrap_or_default()) {
+        let Some(action) = ini.section(Some(format!("Desktop Action {id}"))) else {
+            report
+                .problems
+                .push(format!("action {id} has no [Desktop Action {id}] group"));
+            continue;
+        };
+        check_action(&id, action, environment, &mut report.problems);
+    }
+    report
+}
+
+fn check_action(
+    id: &str,
+    action: &Properties,
+    environment: &Environment,
+    problems: &mut Vec<String>,
+) {
+    if environment.localized(action, "Name").is_none() {
+        problems.push(format!("action {id} has no Name key"));
+    }
+    match action.get("Exec") {
+        None => problems.push(format!("action {id} has no Exec key")),
+        Some(exec) if exec.trim().is_empty() => {
+            problems.push(format!("Exec of action {id} is empty"))
+        }
+        Some(exec) => check_exec(&format!("Exec of action {id}"), &unescape(exec), problems),
+    }
+}
+
+/// Reports an Exec key that cannot be split into arguments, and the field codes that are removed
+/// from it when launching.
+fn check_exec(key: &str, exec: &str, problems: &mut Vec<String>) {
+    if parse_exec(exec).is_none() {
+        problems.push(format!("{key} cannot be split into arguments"));
+    }
+    let (unknown, trailing) = malformed_field_codes(exec);
+    for code in unknown {
+        problems.push(format!("{key} has the unknown field code %{code}"));
+    }
+    if trailing {
+        problems.push(format!("{key} ends with a lone %"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::DesktopEntry;
+
+    /// Checks a desktop file, asserting that it is listed exactly when reading it yields a shown
+    /// entry.
+    fn problems(source: &str) -> (Vec<String>, bool) {
+        let environment = Environment {
+            desktops: vec!["GNOME".to_owned()],
+            ..Default::default()
+        };
+        let report = check(source, &environment);
+        let read = load_source(source).ok().and_then(|ini| {
+            DesktopEntry::from_ini("app", Path::new("/apps/app.desktop"), ini, &environment).ok()
+        });
+        assert_eq!(report.listed, read.is_some_and(|entry| !entry.hide()));
+        (report.problems, report.listed)
+    }
+
+    #[test]
+    fn valid_file() {
+        let source = "[Desktop Entry]\nType=Application\nName=App\nExec=app %F\n\
+                      Actions=new;\n[Desktop Action new]\nName=New\nExec=app --new\n";
+        assert_eq!(problems(source), (vec![], true));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let source = "[Desktop Entry]\nType=Service\nExec=app \"%f %z %\n\
+                      NoDisplay=true\nNotShowIn=GNOME;\nTryExec=/nonexistent/app\n\
+                      Actions=new;gone;\n[Desktop Action new]\nExec=\n";
+        let (found, listed) = problems(source);
+        assert!(!listed);
+        assert_eq!(
+            found,
+            [
+                "Type is Service rather than Application",
+                "no Name key",
+                "Exec cannot be split into arguments",
+                "Exec has the unknown field code %z",
+                "Exec ends with a lone %",
+                "NoDisplay is true",
+                "not shown in the current desktop",
+                "TryExec /nonexistent/app was not found",
+                "action new has no Name key",
+                "Exec of action new is empty",
+                "action gone has no [Desktop Action gone] group",
+            ]
+        );
+    }
+
+    #[test]
+    fn listed_despite_problems() {
+        assert_eq!(
+            problems("[Desktop Entry]\nType=Application\nName=App\nExec=app %z\n"),
+            (vec!["Exec has the unknown field code %z".to_owned()], true)
+        );
+        assert_eq!(
+            problems("Name=App\n"),
+            (vec!["no [Desktop Entry] group".to_owned()], false)
+        );
+    }
+}